@@ -0,0 +1,181 @@
+//! Prints syntax trees as S-expressions, for `jlox --ast`. Expressions are printed on one line,
+//! like `(* (group (+ 1 2)) 3)`, while statements that contain other statements put each of them
+//! on its own, more deeply indented, line.
+
+use crate::parser::{Expr, LiteralValue, Stmt};
+
+use std::iter;
+
+const INDENT: &str = "  ";
+
+pub fn print_program(statements: &[Stmt]) -> String {
+    let mut printer = AstPrinter::default();
+    for statement in statements {
+        printer.stmt(statement);
+        printer.output.push('\n');
+    }
+    printer.output
+}
+
+pub fn print_expr(expr: &Expr) -> String {
+    let mut printer = AstPrinter::default();
+    printer.expr(expr);
+    printer.output
+}
+
+#[derive(Debug, Default)]
+struct AstPrinter {
+    output: String,
+    depth: usize,
+}
+
+impl AstPrinter {
+    fn stmt(&mut self, statement: &Stmt) {
+        self.output.push_str(&INDENT.repeat(self.depth));
+
+        match statement {
+            Stmt::Expression(expr) => self.parenthesize(";", &[expr]),
+            Stmt::Print { values, .. } => {
+                self.parenthesize("print", &values.iter().collect::<Vec<_>>())
+            }
+            Stmt::Var { name, initializer } => {
+                self.output.push_str("(var ");
+                self.output.push_str(&name.lexeme);
+                if let Some(initializer) = initializer {
+                    self.output.push(' ');
+                    self.expr(initializer);
+                }
+                self.output.push(')');
+            }
+            Stmt::Block(statements) => {
+                self.output.push_str("(block");
+                self.nested(statements.iter());
+                self.output.push(')');
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.output.push_str("(if ");
+                self.expr(condition);
+                self.nested(iter::once(&**then_branch).chain(else_branch.as_deref()));
+                self.output.push(')');
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
+                self.output.push_str("(while ");
+                self.expr(condition);
+                if let Some(increment) = increment {
+                    self.output.push(' ');
+                    self.parenthesize("step", &[increment]);
+                }
+                self.nested(iter::once(&**body));
+                self.output.push(')');
+            }
+            Stmt::Empty => self.output.push_str("(;)"),
+            Stmt::Break => self.output.push_str("(break)"),
+            Stmt::Continue => self.output.push_str("(continue)"),
+            Stmt::Function(declaration) => {
+                self.output.push_str("(fun ");
+                self.output.push_str(&declaration.name.lexeme);
+                self.output.push_str(" (");
+                let params: Vec<_> = declaration
+                    .params
+                    .iter()
+                    .map(|param| param.lexeme.as_str())
+                    .collect();
+                self.output.push_str(&params.join(" "));
+                self.output.push(')');
+                self.nested(declaration.body.iter());
+                self.output.push(')');
+            }
+            Stmt::Return { value, .. } => match value {
+                Some(value) => self.parenthesize("return", &[value]),
+                None => self.output.push_str("(return)"),
+            },
+        }
+    }
+
+    /// Prints each of `statements` on a line of its own, one level deeper.
+    fn nested<'a>(&mut self, statements: impl Iterator<Item = &'a Stmt>) {
+        self.depth += 1;
+        for statement in statements {
+            self.output.push('\n');
+            self.stmt(statement);
+        }
+        self.depth -= 1;
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { left, op, right } | Expr::Logical { left, op, right } => {
+                self.parenthesize(&op.lexeme, &[&**left, &**right])
+            }
+            Expr::Unary { op, right } => self.parenthesize(&op.lexeme, &[&**right]),
+            Expr::Literal(value) => match value {
+                LiteralValue::Number { raw, .. } => self.output.push_str(raw),
+                LiteralValue::String(s) => self.output.push_str(&format!("{s:?}")),
+                LiteralValue::Bool(b) => self.output.push_str(&b.to_string()),
+                LiteralValue::Nil => self.output.push_str("nil"),
+            },
+            Expr::Grouping(expr) => self.parenthesize("group", &[&**expr]),
+            Expr::Call { callee, args, .. } => {
+                let mut operands = vec![&**callee];
+                operands.extend(args);
+                self.parenthesize("call", &operands);
+            }
+            Expr::Variable { name, .. } => self.output.push_str(&name.lexeme),
+            Expr::Assign { name, value, .. } => {
+                self.output.push_str("(= ");
+                self.output.push_str(&name.lexeme);
+                self.output.push(' ');
+                self.expr(value);
+                self.output.push(')');
+            }
+        }
+    }
+
+    fn parenthesize(&mut self, name: &str, exprs: &[&Expr]) {
+        self.output.push('(');
+        self.output.push_str(name);
+        for expr in exprs {
+            self.output.push(' ');
+            self.expr(expr);
+        }
+        self.output.push(')');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::{statements_of, tokens_of};
+
+    #[test]
+    fn expression() {
+        let expr = Parser::new(&tokens_of("(1 + 2) * 3")).parse().unwrap();
+        assert_eq!(print_expr(&expr), "(* (group (+ 1 2)) 3)");
+    }
+
+    #[test]
+    fn literals_print_as_written() {
+        assert_eq!(
+            print_program(&statements_of("print 1.50, \"a\\tb\", true, nil;")),
+            "(print 1.50 \"a\\tb\" true nil)\n"
+        );
+    }
+
+    #[test]
+    fn nested_statements_are_indented() {
+        let code = "fun f(a, b) {\n  if (a) { return b; } else print -a;\n}\nvar x = f(1, 2);";
+        assert_eq!(
+            print_program(&statements_of(code)),
+            "(fun f (a b)\n  (if a\n    (block\n      (return b))\n    (print (- a))))\n(var x (call f 1 2))\n"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use crate::interpreter::{RuntimeError, Value};
+use crate::Token;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The variables declared in one scope, by name, plus the scope enclosing it. Lookups that miss
+/// in this scope continue outwards.
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<String, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// Binds `name` to `value`, replacing any existing binding so that variables can be
+    /// redeclared.
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.values.insert(name.to_owned(), value);
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value, RuntimeError> {
+        match (self.values.get(&name.lexeme), &self.enclosing) {
+            (Some(value), _) => Ok(value.clone()),
+            (None, Some(enclosing)) => enclosing.borrow().get(name),
+            (None, None) => Err(Self::undefined(name)),
+        }
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<(), RuntimeError> {
+        match (self.values.get_mut(&name.lexeme), &self.enclosing) {
+            (Some(slot), _) => {
+                *slot = value;
+                Ok(())
+            }
+            (None, Some(enclosing)) => enclosing.borrow_mut().assign(name, value),
+            (None, None) => Err(Self::undefined(name)),
+        }
+    }
+
+    /// Like `get`, but looking only in the scope `depth` scopes out from this one, where the
+    /// resolver found the variable's declaration.
+    pub fn get_at(&self, depth: usize, name: &Token) -> Result<Value, RuntimeError> {
+        match (depth, &self.enclosing) {
+            (0, _) => self
+                .values
+                .get(&name.lexeme)
+                .cloned()
+                .ok_or_else(|| Self::undefined(name)),
+            (_, Some(enclosing)) => enclosing.borrow().get_at(depth - 1, name),
+            (_, None) => Err(Self::undefined(name)),
+        }
+    }
+
+    pub fn assign_at(
+        &mut self,
+        depth: usize,
+        name: &Token,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        match (depth, &self.enclosing) {
+            (0, _) => match self.values.get_mut(&name.lexeme) {
+                Some(slot) => {
+                    *slot = value;
+                    Ok(())
+                }
+                None => Err(Self::undefined(name)),
+            },
+            (_, Some(enclosing)) => enclosing.borrow_mut().assign_at(depth - 1, name, value),
+            (_, None) => Err(Self::undefined(name)),
+        }
+    }
+
+    fn undefined(name: &Token) -> RuntimeError {
+        RuntimeError::new(name, &format!("Undefined variable '{}'.", name.lexeme))
+    }
+}
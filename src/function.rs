@@ -0,0 +1,135 @@
+use crate::environment::Environment;
+use crate::interpreter::{Interpreter, RuntimeError, Unwind, Value};
+use crate::parser::FunctionDecl;
+use crate::Token;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// A function value: a declaration together with the environment it was declared in, so that it
+/// can see the variables that were in scope there.
+///
+/// That environment usually holds the function itself, under its name, so the two keep each other
+/// alive and are never freed. This is a known leak, left in place since functions live for most of
+/// a run anyway; fixing it needs a garbage collector or weak references to environments, as
+/// closures returned from their enclosing function must keep it alive.
+pub struct Function {
+    declaration: Rc<FunctionDecl>,
+    closure: Rc<RefCell<Environment>>,
+}
+
+impl Function {
+    pub fn new(declaration: Rc<FunctionDecl>, closure: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            declaration,
+            closure,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.declaration.name.lexeme
+    }
+
+    pub fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    /// Runs the body with `args` bound to the parameters, which the caller has already checked
+    /// match the arity.
+    pub fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        for (param, arg) in self.declaration.params.iter().zip(args) {
+            environment.define(&param.lexeme, arg);
+        }
+
+        match interpreter.execute_block(&self.declaration.body, environment) {
+            Ok(()) => Ok(Value::Nil),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(error)) => Err(error),
+            Err(Unwind::Break | Unwind::Continue) => {
+                unreachable!("the parser doesn't allow 'break' or 'continue' to leave a function")
+            }
+        }
+    }
+}
+
+/// Functions are only equal to themselves.
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Not derived, since the closure may contain the function itself.
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name.lexeme)
+    }
+}
+
+/// The Rust side of a native function, given the call's arguments. An `Err` is reported as a
+/// runtime error with that message, at the call.
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, String>;
+
+/// A function implemented in Rust and provided by the host, such as `clock`.
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            arity,
+            function: Box::new(function),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Runs the function with `args`, reporting any failure at the call's closing `paren`.
+    pub fn call(&self, paren: &Token, args: &[Value]) -> Result<Value, RuntimeError> {
+        (self.function)(args).map_err(|message| RuntimeError::new(paren, &message))
+    }
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+impl fmt::Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
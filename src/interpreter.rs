@@ -0,0 +1,970 @@
+use crate::environment::Environment;
+use crate::function::{Function, NativeFunction};
+use crate::parser::{Expr, LiteralValue, Stmt};
+use crate::{Diagnostic, Phase, Token, TokenKind};
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Nil,
+    Function(Rc<Function>),
+    Native(Rc<NativeFunction>),
+}
+
+impl Value {
+    /// Lox's truthiness: `nil` and `false` are falsey, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // `f64`'s `Display` already omits the fractional part of integral values
+            Value::Number(n) => write!(f, "{n}"),
+            Value::String(s) => write!(f, "{s}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Nil => write!(f, "nil"),
+            Value::Function(function) => write!(f, "{function}"),
+            Value::Native(native) => write!(f, "{native}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub token: Token,
+    pub message: String,
+    /// What went wrong, if the error is a failure to write the program's output
+    pub output_error: Option<io::ErrorKind>,
+}
+
+impl RuntimeError {
+    pub fn new(token: &Token, message: &str) -> Self {
+        Self {
+            token: token.clone(),
+            message: message.to_owned(),
+            output_error: None,
+        }
+    }
+
+    /// The error for when the `print` at `keyword` couldn't write its output.
+    fn output(keyword: &Token, error: &io::Error) -> Self {
+        Self {
+            output_error: Some(error.kind()),
+            ..Self::new(
+                keyword,
+                &format!("Could not write program output: {error}."),
+            )
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(error: &RuntimeError) -> Self {
+        Diagnostic::error(Phase::Runtime, error.token.line, "", &error.message)
+            .with_span(error.token.span())
+    }
+}
+
+/// Why execution of a statement stopped early: either an error, or a `return`, `break` or
+/// `continue` unwinding to the enclosing call or loop.
+#[derive(Debug)]
+pub enum Unwind {
+    Error(RuntimeError),
+    Return(Value),
+    /// A `break` unwinding to the innermost loop
+    Break,
+    /// A `continue` unwinding to the innermost loop
+    Continue,
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+pub struct Interpreter {
+    /// The outermost environment, where variables the resolver didn't resolve are looked up
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    strict_numeric: bool,
+    /// How many Lox function calls are currently in progress
+    call_depth: usize,
+    max_call_depth: usize,
+    /// The address of the native stack where the outermost call in progress began, and how far
+    /// past it nested calls may use the stack
+    stack_base: usize,
+    max_stack_size: usize,
+    /// Where `print` writes to
+    output: Box<dyn Write>,
+}
+
+impl fmt::Debug for Interpreter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interpreter")
+            .field("globals", &self.globals)
+            .field("environment", &self.environment)
+            .field("strict_numeric", &self.strict_numeric)
+            .field("call_depth", &self.call_depth)
+            .field("max_call_depth", &self.max_call_depth)
+            .field("max_stack_size", &self.max_stack_size)
+            .finish_non_exhaustive()
+    }
+}
+
+/// How deeply Lox function calls may nest by default before a call fails with a stack overflow
+/// error. How much native stack that takes varies a lot between builds, so calls are also limited
+/// by [`DEFAULT_MAX_STACK_SIZE`].
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// How many bytes of native stack nested Lox calls may use by default before a call fails with a
+/// stack overflow error, rather than deep recursion aborting the whole process. Each Lox call
+/// takes several Rust frames, which add up to tens of kilobytes in debug builds, so this leaves
+/// plenty of headroom on a 2 MiB thread (the smallest that Rust spawns by default).
+pub const DEFAULT_MAX_STACK_SIZE: usize = 1024 * 1024;
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// An interpreter whose global environment holds the built-in natives.
+    pub fn new() -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        let mut interpreter = Self {
+            environment: Rc::clone(&globals),
+            globals,
+            strict_numeric: false,
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            stack_base: 0,
+            max_stack_size: DEFAULT_MAX_STACK_SIZE,
+            output: Box::new(io::stdout()),
+        };
+
+        interpreter.define_native("clock", 0, |_| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            Ok(Value::Number(now.as_secs_f64()))
+        });
+
+        interpreter
+    }
+
+    /// Defines a global function `name` implemented by `function`, which is only ever called with
+    /// exactly `arity` arguments. This is how hosts can give scripts extra capabilities. If
+    /// `function` returns an error, the call fails with that message as a runtime error.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) {
+        let native = NativeFunction::new(name, arity, function);
+        self.globals
+            .borrow_mut()
+            .define(name, Value::Native(Rc::new(native)));
+    }
+
+    /// When enabled, arithmetic that produces NaN or an infinity is a runtime error instead of
+    /// following IEEE 754.
+    pub fn with_strict_numeric(mut self, strict_numeric: bool) -> Self {
+        self.strict_numeric = strict_numeric;
+        self
+    }
+
+    /// Limits how many Lox calls may be in progress at once. Hosts running untrusted code can lower
+    /// it, while deeply recursive programs may need it raised, along with the max stack size.
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    /// Limits how many bytes of native stack nested Lox calls may use. This must be comfortably
+    /// less than the stack of the thread that runs the interpreter, so hosts that run it on a
+    /// thread with a bigger stack can raise it to allow deeper recursion.
+    pub fn with_max_stack_size(mut self, max_stack_size: usize) -> Self {
+        self.max_stack_size = max_stack_size;
+        self
+    }
+
+    /// Sends what the program prints to `output` rather than stdout, so that hosts (and tests)
+    /// can capture it.
+    pub fn with_output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Runs a program. Its statements should already have been through the [`Resolver`], as
+    /// without it every variable is looked up as a global, and static errors such as a `return`
+    /// outside of a function are only caught when they run.
+    ///
+    /// [`Resolver`]: crate::resolver::Resolver
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), RuntimeError> {
+        for statement in statements {
+            match self.execute(statement) {
+                Ok(()) => {}
+                Err(Unwind::Error(error)) => return Err(error),
+                Err(Unwind::Return(_)) => {
+                    unreachable!("'return' outside of a call is a runtime error")
+                }
+                Err(Unwind::Break | Unwind::Continue) => {
+                    unreachable!("the parser only allows 'break' and 'continue' in loops")
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn execute(&mut self, statement: &Stmt) -> Result<(), Unwind> {
+        match statement {
+            Stmt::Expression(expr) => {
+                self.evaluate(expr)?;
+            }
+            Stmt::Print { keyword, values } => {
+                let values = values
+                    .iter()
+                    .map(|expr| self.evaluate(expr).map(|value| value.to_string()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                writeln!(self.output, "{}", values.join(" "))
+                    .map_err(|error| RuntimeError::output(keyword, &error))?;
+            }
+            Stmt::Var { name, initializer } => {
+                let value = match initializer {
+                    Some(initializer) => self.evaluate(initializer)?,
+                    None => Value::Nil,
+                };
+                self.environment.borrow_mut().define(&name.lexeme, value);
+            }
+            Stmt::Block(statements) => {
+                let environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                self.execute_block(statements, environment)?;
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if self.evaluate(condition)?.is_truthy() {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
+                while self.evaluate(condition)?.is_truthy() {
+                    match self.execute(body) {
+                        Ok(()) | Err(Unwind::Continue) => {}
+                        Err(Unwind::Break) => break,
+                        Err(unwind) => return Err(unwind),
+                    }
+
+                    if let Some(increment) = increment {
+                        self.evaluate(increment)?;
+                    }
+                }
+            }
+            Stmt::Break => return Err(Unwind::Break),
+            Stmt::Continue => return Err(Unwind::Continue),
+            Stmt::Empty => {}
+            Stmt::Function(declaration) => {
+                let function = Function::new(Rc::clone(declaration), Rc::clone(&self.environment));
+                self.environment
+                    .borrow_mut()
+                    .define(&declaration.name.lexeme, Value::Function(Rc::new(function)));
+            }
+            Stmt::Return { keyword, value } => {
+                // The resolver rejects this, but the program may not have been resolved
+                if self.call_depth == 0 {
+                    let error = RuntimeError::new(keyword, "Can't return from top-level code.");
+                    return Err(error.into());
+                }
+
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Nil,
+                };
+                return Err(Unwind::Return(value));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Executes `statements` in `environment`, restoring the current environment afterwards even
+    /// if one of them fails.
+    pub fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Environment,
+    ) -> Result<(), Unwind> {
+        let previous = mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement));
+        self.environment = previous;
+
+        result
+    }
+
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match expr {
+            Expr::Literal(value) => Ok(match value {
+                LiteralValue::Number { value, .. } => Value::Number(*value),
+                LiteralValue::String(s) => Value::String(s.clone()),
+                LiteralValue::Bool(b) => Value::Bool(*b),
+                LiteralValue::Nil => Value::Nil,
+            }),
+            Expr::Grouping(expr) => self.evaluate(expr),
+            Expr::Logical { left, op, right } => {
+                let left = self.evaluate(left)?;
+
+                // The result is whichever operand decided it, not a coerced bool
+                let decided = match op.kind {
+                    TokenKind::Or => left.is_truthy(),
+                    TokenKind::And => !left.is_truthy(),
+                    _ => unreachable!("invalid logical operator {:?}", op.kind),
+                };
+
+                if decided {
+                    Ok(left)
+                } else {
+                    self.evaluate(right)
+                }
+            }
+            Expr::Call {
+                callee,
+                paren,
+                args,
+            } => {
+                let callee = self.evaluate(callee)?;
+                let args = args
+                    .iter()
+                    .map(|arg| self.evaluate(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                match callee {
+                    Value::Function(function) => {
+                        Self::check_arity(paren, function.name(), function.arity(), args.len())?;
+                        self.check_stack(paren)?;
+
+                        self.call_depth += 1;
+                        let result = function.call(self, args);
+                        self.call_depth -= 1;
+                        result
+                    }
+                    Value::Native(native) => {
+                        Self::check_arity(paren, native.name(), native.arity(), args.len())?;
+                        native.call(paren, &args)
+                    }
+                    _ => Err(RuntimeError::new(
+                        paren,
+                        "Can only call functions and classes.",
+                    )),
+                }
+            }
+            Expr::Variable { name, depth } => match depth.get() {
+                Some(depth) => self.environment.borrow().get_at(depth, name),
+                None => self.globals.borrow().get(name),
+            },
+            Expr::Assign { name, value, depth } => {
+                let value = self.evaluate(value)?;
+                match depth.get() {
+                    Some(depth) => {
+                        self.environment
+                            .borrow_mut()
+                            .assign_at(depth, name, value.clone())?
+                    }
+                    None => self.globals.borrow_mut().assign(name, value.clone())?,
+                }
+                Ok(value)
+            }
+            Expr::Unary { op, right } => {
+                let right = self.evaluate(right)?;
+
+                match op.kind {
+                    TokenKind::Minus => {
+                        let value = Value::Number(-Self::number_operand(op, &right)?);
+                        self.check_finite(op, value)
+                    }
+                    TokenKind::Bang => Ok(Value::Bool(!right.is_truthy())),
+                    _ => unreachable!("invalid unary operator {:?}", op.kind),
+                }
+            }
+            Expr::Binary { left, op, right } => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+
+                let value = Self::binary(op, left, right)?;
+                self.check_finite(op, value)
+            }
+        }
+    }
+
+    /// Fails unless a call to the function `name` passes it exactly `arity` arguments.
+    fn check_arity(
+        paren: &Token,
+        name: &str,
+        arity: usize,
+        args: usize,
+    ) -> Result<(), RuntimeError> {
+        if args == arity {
+            return Ok(());
+        }
+
+        let arguments = if arity == 1 { "argument" } else { "arguments" };
+        Err(RuntimeError::new(
+            paren,
+            &format!("Expected {arity} {arguments} to '{name}' but got {args}."),
+        ))
+    }
+
+    /// Fails if there isn't room for another call, whether because of the max call depth or
+    /// because the native stack is running out.
+    fn check_stack(&mut self, paren: &Token) -> Result<(), RuntimeError> {
+        if self.call_depth == self.max_call_depth {
+            let message = format!(
+                "Stack overflow: more than {} nested calls.",
+                self.max_call_depth
+            );
+            return Err(RuntimeError::new(paren, &message));
+        }
+
+        // Measured from a local variable, which is on the stack wherever the stack grows
+        let marker = 0u8;
+        let here = std::ptr::addr_of!(marker) as usize;
+        if self.call_depth == 0 {
+            self.stack_base = here;
+        } else if self.stack_base.abs_diff(here) > self.max_stack_size {
+            let message = format!(
+                "Stack overflow: out of native stack after {} nested calls.",
+                self.call_depth
+            );
+            return Err(RuntimeError::new(paren, &message));
+        }
+
+        Ok(())
+    }
+
+    fn check_finite(&self, op: &Token, value: Value) -> Result<Value, RuntimeError> {
+        match value {
+            Value::Number(n) if self.strict_numeric && !n.is_finite() => Err(RuntimeError::new(
+                op,
+                &format!("Arithmetic produced {n}, which is not a finite number."),
+            )),
+            _ => Ok(value),
+        }
+    }
+
+    fn binary(op: &Token, left: Value, right: Value) -> Result<Value, RuntimeError> {
+        use TokenKind as TK;
+
+        let value = match op.kind {
+            TK::Plus => match (left, right) {
+                (Value::Number(l), Value::Number(r)) => Value::Number(l + r),
+                (Value::String(l), Value::String(r)) => Value::String(l + &r),
+                _ => {
+                    return Err(RuntimeError::new(
+                        op,
+                        "Operands must be two numbers or two strings.",
+                    ))
+                }
+            },
+            TK::EqualEqual => Value::Bool(left == right),
+            TK::BangEqual => Value::Bool(left != right),
+            _ => {
+                let (l, r) = Self::number_operands(op, &left, &right)?;
+
+                match op.kind {
+                    TK::Minus => Value::Number(l - r),
+                    TK::Star => Value::Number(l * r),
+                    TK::Slash if r == 0.0 => {
+                        return Err(RuntimeError::new(op, "Division by zero."))
+                    }
+                    TK::Slash => Value::Number(l / r),
+                    TK::Greater => Value::Bool(l > r),
+                    TK::GreaterEqual => Value::Bool(l >= r),
+                    TK::Less => Value::Bool(l < r),
+                    TK::LessEqual => Value::Bool(l <= r),
+                    _ => unreachable!("invalid binary operator {:?}", op.kind),
+                }
+            }
+        };
+
+        Ok(value)
+    }
+
+    fn number_operand(op: &Token, operand: &Value) -> Result<f64, RuntimeError> {
+        match operand {
+            Value::Number(n) => Ok(*n),
+            _ => Err(RuntimeError::new(op, "Operand must be a number.")),
+        }
+    }
+
+    fn number_operands(
+        op: &Token,
+        left: &Value,
+        right: &Value,
+    ) -> Result<(f64, f64), RuntimeError> {
+        match (left, right) {
+            (Value::Number(l), Value::Number(r)) => Ok((*l, *r)),
+            _ => Err(RuntimeError::new(op, "Operands must be numbers.")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+    use crate::{statements_of, tokens_of};
+
+    /// Runs `code`, which must be free of static errors, in `interpreter`.
+    fn run(interpreter: &mut Interpreter, code: &str) -> Result<(), RuntimeError> {
+        let statements = statements_of(code);
+        Resolver::new().resolve(&statements).unwrap();
+        interpreter.interpret(&statements)
+    }
+
+    /// Program output that can still be read after it has been given to an interpreter.
+    #[derive(Debug, Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// What `code` prints when run in a fresh interpreter, which it must do without errors.
+    fn output_of(code: &str) -> String {
+        let output = Output::default();
+        let mut interpreter = Interpreter::new().with_output(output.clone());
+        run(&mut interpreter, code).unwrap();
+
+        let bytes = output.0.borrow();
+        String::from_utf8(bytes.clone()).unwrap()
+    }
+
+    /// Evaluates the expression `code` in a fresh interpreter.
+    fn eval(code: &str) -> Result<Value, RuntimeError> {
+        let expr = Parser::new(&tokens_of(code)).parse().unwrap();
+        Interpreter::new().evaluate(&expr)
+    }
+
+    fn eval_error(code: &str) -> String {
+        eval(code).unwrap_err().message
+    }
+
+    /// A program that nests `depth` calls.
+    fn recursion(depth: usize) -> String {
+        format!("fun recurse(n) {{ if (n > 1) recurse(n - 1); }} recurse({depth});")
+    }
+
+    #[test]
+    fn calls_within_the_max_call_depth_succeed() {
+        let mut interpreter = Interpreter::new().with_max_call_depth(10);
+        assert_eq!(run(&mut interpreter, &recursion(9)), Ok(()));
+        assert_eq!(run(&mut interpreter, &recursion(10)), Ok(()));
+    }
+
+    #[test]
+    fn calls_beyond_the_max_call_depth_overflow() {
+        let mut interpreter = Interpreter::new().with_max_call_depth(10);
+        let error = run(&mut interpreter, &recursion(11)).unwrap_err();
+        assert_eq!(error.message, "Stack overflow: more than 10 nested calls.");
+
+        // The failed calls don't count against later ones
+        assert_eq!(run(&mut interpreter, &recursion(10)), Ok(()));
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(eval("1 + 2 * 3 - 4 / 2"), Ok(Value::Number(5.0)));
+        assert_eq!(eval("-(1 + 2)"), Ok(Value::Number(-3.0)));
+        assert_eq!(eval("2 < 3"), Ok(Value::Bool(true)));
+        assert_eq!(eval("2 >= 3"), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn string_concatenation() {
+        assert_eq!(
+            eval(r#""foo" + "bar""#),
+            Ok(Value::String("foobar".to_owned()))
+        );
+    }
+
+    #[test]
+    fn only_nil_and_false_are_falsey() {
+        assert_eq!(eval("!nil"), Ok(Value::Bool(true)));
+        assert_eq!(eval("!false"), Ok(Value::Bool(true)));
+        assert_eq!(eval("!0"), Ok(Value::Bool(false)));
+        assert_eq!(eval(r#"!"""#), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn equality_never_coerces() {
+        assert_eq!(eval("nil == nil"), Ok(Value::Bool(true)));
+        assert_eq!(eval(r#"1 == "1""#), Ok(Value::Bool(false)));
+        assert_eq!(eval("nil != false"), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn operand_type_errors() {
+        assert_eq!(eval_error("-\"a\""), "Operand must be a number.");
+        assert_eq!(eval_error("1 * nil"), "Operands must be numbers.");
+        assert_eq!(
+            eval_error(r#"1 + "a""#),
+            "Operands must be two numbers or two strings."
+        );
+        assert_eq!(eval_error("1 / 0"), "Division by zero.");
+    }
+
+    #[test]
+    fn print_statement() {
+        assert_eq!(output_of("print 1 + 2;"), "3\n");
+        assert_eq!(output_of("print \"a\";\nprint nil;"), "a\nnil\n");
+    }
+
+    #[test]
+    fn expression_statement_discards_its_value() {
+        assert_eq!(output_of("1 + 2;"), "");
+    }
+
+    /// The message of the runtime error that `code` fails with.
+    fn runtime_error(code: &str) -> String {
+        let mut interpreter = Interpreter::new().with_output(Output::default());
+        run(&mut interpreter, code).unwrap_err().message
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(output_of("var a;\nprint a;"), "nil\n");
+        assert_eq!(output_of("var a = 1;\nprint a;"), "1\n");
+        assert_eq!(output_of("var a = 1;\na = a + 1;\nprint a;"), "2\n");
+        assert_eq!(
+            output_of("var a = 1;\nvar a = \"again\";\nprint a;"),
+            "again\n"
+        );
+        assert_eq!(
+            output_of("var a;\nvar b;\nprint a = b = 3;\nprint a;"),
+            "3\n3\n"
+        );
+    }
+
+    #[test]
+    fn undefined_variables() {
+        assert_eq!(runtime_error("print nope;"), "Undefined variable 'nope'.");
+        assert_eq!(runtime_error("nope = 1;"), "Undefined variable 'nope'.");
+    }
+
+    #[test]
+    fn blocks_shadow_outer_variables() {
+        let code = "var a = \"outer\";\n{\n  var a = \"inner\";\n  print a;\n}\nprint a;";
+        assert_eq!(output_of(code), "inner\nouter\n");
+    }
+
+    #[test]
+    fn assignment_in_a_block_changes_the_outer_variable() {
+        let code = "var a = 1;\n{\n  a = 2;\n  { a = a + 1; }\n}\nprint a;";
+        assert_eq!(output_of(code), "3\n");
+    }
+
+    #[test]
+    fn block_variables_end_with_the_block() {
+        assert_eq!(
+            runtime_error("{ var a = 1; }\nprint a;"),
+            "Undefined variable 'a'."
+        );
+    }
+
+    #[test]
+    fn if_takes_one_branch() {
+        assert_eq!(output_of("if (true) print 1; else print 2;"), "1\n");
+        assert_eq!(output_of("if (nil) print 1; else print 2;"), "2\n");
+        assert_eq!(output_of("if (false) print 1;"), "");
+    }
+
+    #[test]
+    fn dangling_else_belongs_to_the_nearest_if() {
+        assert_eq!(
+            output_of("if (true) if (false) print 1; else print 2;"),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn while_loop_counts() {
+        let code =
+            "var i = 0;\nvar sum = 0;\nwhile (i < 5) { i = i + 1; sum = sum + i; }\nprint sum;";
+        assert_eq!(output_of(code), "15\n");
+    }
+
+    /// `big * big` overflows to infinity.
+    fn overflow(code: &str) -> String {
+        format!("var big = 1{};\n{code}", "0".repeat(200))
+    }
+
+    #[test]
+    fn non_finite_arithmetic_follows_ieee_754_by_default() {
+        let code = overflow("print big * big;\nprint big * big - big * big;");
+        assert_eq!(output_of(&code), "inf\nNaN\n");
+    }
+
+    #[test]
+    fn strict_numeric_rejects_non_finite_results() {
+        let mut interpreter = Interpreter::new()
+            .with_strict_numeric(true)
+            .with_output(Output::default());
+        let error = run(&mut interpreter, &overflow("print big * big;")).unwrap_err();
+
+        assert_eq!(
+            error.message,
+            "Arithmetic produced inf, which is not a finite number."
+        );
+        assert_eq!(error.token.kind, TokenKind::Star);
+    }
+
+    #[test]
+    fn zero_divided_by_zero_is_an_error_in_either_mode() {
+        for strict_numeric in [false, true] {
+            let mut interpreter = Interpreter::new().with_strict_numeric(strict_numeric);
+            let error = run(&mut interpreter, "print 0.0 / 0.0;").unwrap_err();
+            assert_eq!(error.message, "Division by zero.");
+        }
+    }
+
+    #[test]
+    fn for_loop_counts() {
+        let code = "for (var i = 0; i < 3; i = i + 1) print i;";
+        assert_eq!(output_of(code), "0\n1\n2\n");
+    }
+
+    #[test]
+    fn for_loop_variable_is_scoped_to_the_loop() {
+        assert_eq!(
+            runtime_error("for (var i = 0; i < 1; i = i + 1) {}\nprint i;"),
+            "Undefined variable 'i'."
+        );
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        // The right operand would fail, since `nope` is undefined, if it were evaluated
+        assert_eq!(output_of("print false and nope;"), "false\n");
+        assert_eq!(output_of("print 1 or nope;"), "1\n");
+        assert_eq!(
+            runtime_error("print true and nope;"),
+            "Undefined variable 'nope'."
+        );
+    }
+
+    #[test]
+    fn logical_operators_return_the_deciding_operand() {
+        assert_eq!(output_of("print nil or \"default\";"), "default\n");
+        assert_eq!(output_of("print 1 and 2;"), "2\n");
+        assert_eq!(output_of("print nil and 2;"), "nil\n");
+    }
+
+    #[test]
+    fn values_display_like_lox() {
+        assert_eq!(Value::Number(5.0).to_string(), "5");
+        assert_eq!(Value::Number(5.5).to_string(), "5.5");
+        assert_eq!(Value::Number(-0.25).to_string(), "-0.25");
+        assert_eq!(Value::String("text".to_owned()).to_string(), "text");
+        assert_eq!(Value::Bool(true).to_string(), "true");
+        assert_eq!(Value::Nil.to_string(), "nil");
+    }
+
+    #[test]
+    fn print_joins_several_values_with_spaces() {
+        assert_eq!(output_of("print 1, 2, 3;"), "1 2 3\n");
+        assert_eq!(output_of("print \"a\", nil, 1 + 1;"), "a nil 2\n");
+    }
+
+    #[test]
+    fn print_evaluates_every_value_before_printing() {
+        let code = "var a = 1;\nprint a, a = 2, a;";
+        assert_eq!(output_of(code), "1 2 2\n");
+
+        // Nothing is printed if one of the values fails
+        let output = Output::default();
+        let mut interpreter = Interpreter::new().with_output(output.clone());
+        assert!(run(&mut interpreter, "print 1, nope;").is_err());
+        assert_eq!(*output.0.borrow(), b"");
+    }
+
+    #[test]
+    fn recursive_function() {
+        let code = "fun fib(n) {\n  if (n < 2) return n;\n  return fib(n - 1) + fib(n - 2);\n}\nprint fib(10);";
+        assert_eq!(output_of(code), "55\n");
+    }
+
+    #[test]
+    fn closure_counter() {
+        let code = "fun makeCounter() {\n  var i = 0;\n  fun count() {\n    i = i + 1;\n    return i;\n  }\n  return count;\n}\nvar counter = makeCounter();\nprint counter();\nprint counter();\nvar other = makeCounter();\nprint other();";
+        assert_eq!(output_of(code), "1\n2\n1\n");
+    }
+
+    #[test]
+    fn functions_without_a_return_value_give_nil() {
+        let code = "fun f() {}\nfun g() { return; }\nprint f(), g(), f;";
+        assert_eq!(output_of(code), "nil nil <fn f>\n");
+    }
+
+    #[test]
+    fn call_errors() {
+        assert_eq!(
+            runtime_error("fun add(a, b) { return a + b; }\nadd(1);"),
+            "Expected 2 arguments to 'add' but got 1."
+        );
+        assert_eq!(
+            runtime_error("fun add(a, b) { return a + b; }\nadd(1, 2, 3);"),
+            "Expected 2 arguments to 'add' but got 3."
+        );
+        assert_eq!(
+            runtime_error("fun one(a) {}\none();"),
+            "Expected 1 argument to 'one' but got 0."
+        );
+        assert_eq!(
+            runtime_error("clock(1);"),
+            "Expected 0 arguments to 'clock' but got 1."
+        );
+        assert_eq!(
+            runtime_error("\"not a function\"();"),
+            "Can only call functions and classes."
+        );
+    }
+
+    #[test]
+    fn unresolved_top_level_return_is_a_runtime_error() {
+        // As a host that skips the resolver would run it
+        let statements = statements_of("print 1;\nwhile (true) return 2;\nprint 3;");
+
+        let output = Output::default();
+        let mut interpreter = Interpreter::new().with_output(output.clone());
+        let error = interpreter.interpret(&statements).unwrap_err();
+
+        assert_eq!(error.message, "Can't return from top-level code.");
+        assert_eq!(
+            (error.token.lexeme.as_str(), error.token.line),
+            ("return", 2)
+        );
+        assert_eq!(String::from_utf8(output.0.take()).unwrap(), "1\n");
+    }
+
+    #[test]
+    fn unbounded_recursion_is_a_stack_overflow() {
+        let mut interpreter = Interpreter::new().with_max_call_depth(20);
+        let error = run(&mut interpreter, "fun f() { f(); }\nf();").unwrap_err();
+        assert_eq!(error.message, "Stack overflow: more than 20 nested calls.");
+    }
+
+    #[test]
+    fn running_out_of_native_stack_is_a_stack_overflow() {
+        // Without a call depth limit, the stack size limit stops the recursion before the test
+        // thread's stack really overflows
+        let mut interpreter = Interpreter::new().with_max_call_depth(usize::MAX);
+        let error = run(&mut interpreter, "fun f() { f(); }\nf();").unwrap_err();
+        assert!(
+            error
+                .message
+                .starts_with("Stack overflow: out of native stack after"),
+            "{}",
+            error.message
+        );
+
+        let mut interpreter = Interpreter::new()
+            .with_max_call_depth(usize::MAX)
+            .with_max_stack_size(0);
+        let error = run(&mut interpreter, &recursion(2)).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Stack overflow: out of native stack after 1 nested calls."
+        );
+    }
+
+    #[test]
+    fn clock_does_not_go_backwards() {
+        let code = "var first = clock();\nvar second = clock();\nprint second >= first, first > 0;";
+        assert_eq!(output_of(code), "true true\n");
+    }
+
+    #[test]
+    fn host_defined_natives() {
+        let output = Output::default();
+        let mut interpreter = Interpreter::new().with_output(output.clone());
+        interpreter.define_native("half", 1, |args| match &args[0] {
+            Value::Number(n) => Ok(Value::Number(n / 2.0)),
+            _ => Err("half() needs a number.".to_owned()),
+        });
+
+        run(&mut interpreter, "print half(3), half;").unwrap();
+        assert_eq!(
+            String::from_utf8(output.0.take()).unwrap(),
+            "1.5 <native fn>\n"
+        );
+
+        let error = run(&mut interpreter, "half(\"x\");").unwrap_err();
+        assert_eq!(error.message, "half() needs a number.");
+        assert_eq!(error.token.kind, TokenKind::RightParen);
+
+        let error = run(&mut interpreter, "half();").unwrap_err();
+        assert_eq!(error.message, "Expected 1 argument to 'half' but got 0.");
+    }
+
+    #[test]
+    fn closures_keep_the_binding_they_were_declared_with() {
+        let code = "var a = \"global\";\n{\n  fun showA() {\n    print a;\n  }\n\n  showA();\n  var a = \"block\";\n  showA();\n}";
+        assert_eq!(output_of(code), "global\nglobal\n");
+    }
+
+    #[test]
+    fn closures_see_later_assignments_to_captured_variables() {
+        let code = "fun make() {\n  var x = 1;\n  fun get() { return x; }\n  x = 2;\n  return get;\n}\nprint make()();";
+        assert_eq!(output_of(code), "2\n");
+    }
+
+    #[test]
+    fn break_leaves_the_loop_early() {
+        let code = "var i = 0;\nwhile (true) {\n  if (i == 3) break;\n  print i;\n  i = i + 1;\n}\nprint \"done\";";
+        assert_eq!(output_of(code), "0\n1\n2\ndone\n");
+    }
+
+    #[test]
+    fn continue_still_runs_the_for_increment() {
+        let code = "for (var i = 0; i < 6; i = i + 1) {\n  if (i == 0 or i == 2 or i == 4) continue;\n  print i;\n}";
+        assert_eq!(output_of(code), "1\n3\n5\n");
+    }
+
+    #[test]
+    fn break_only_leaves_the_innermost_loop() {
+        let code = "for (var i = 0; i < 2; i = i + 1) {\n  while (true) break;\n  print i;\n}";
+        assert_eq!(output_of(code), "0\n1\n");
+    }
+}
@@ -0,0 +1,2128 @@
+//! A tree-walking interpreter for Lox, the language from Crafting Interpreters. [`Lox`] runs
+//! programs, and reports their errors through whichever [`Diagnostics`] the host provides.
+
+#![allow(unused)]
+
+use anyhow::{Context, Result};
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::str::Chars;
+use std::time::Instant;
+
+mod ast_printer;
+pub mod environment;
+pub mod function;
+pub mod interpreter;
+mod lint;
+pub mod parser;
+pub mod resolver;
+pub mod test_runner;
+
+use interpreter::Interpreter;
+use parser::{Parser, Stmt};
+use resolver::Resolver;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Identifier(String),
+    String(String),
+    Number {
+        value: f64,
+        /// The literal as written in the source, so that tools can reprint it unchanged
+        raw: String,
+    },
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Identifier(name) => write!(f, "{name}"),
+            Literal::String(s) => write!(f, "{s}"),
+            // Printed like a runtime value, so `3.0` shows as `3`
+            Literal::Number { value, .. } => write!(f, "{value}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    // Single-character tokens
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    Comma,
+    Dot,
+    Minus,
+    Plus,
+    Semicolon,
+    Slash,
+    Star,
+    // One or two character tokens
+    Bang,
+    BangEqual,
+    Equal,
+    EqualEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    // Literals
+    Literal(Literal),
+    // Keywords
+    And,
+    Break,
+    Class,
+    Continue,
+    Else,
+    False,
+    Fun,
+    For,
+    If,
+    Nil,
+    Or,
+    Print,
+    Return,
+    Super,
+    This,
+    True,
+    Var,
+    While,
+    // End-of-file
+    Eof,
+}
+
+/// The kind's name as the book's jlox spells it, e.g. `LEFT_PAREN` or `IDENTIFIER`.
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TokenKind::Literal(Literal::Identifier(_)) => return write!(f, "IDENTIFIER"),
+            TokenKind::Literal(Literal::String(_)) => return write!(f, "STRING"),
+            TokenKind::Literal(Literal::Number { .. }) => return write!(f, "NUMBER"),
+            kind => format!("{kind:?}"),
+        };
+
+        // The other kinds' variant names, with each word break marked by an underscore
+        for (i, c) in name.chars().enumerate() {
+            if i > 0 && c.is_ascii_uppercase() {
+                write!(f, "_")?;
+            }
+            write!(f, "{}", c.to_ascii_uppercase())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The stage of the pipeline that produced a [`Diagnostic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Scan,
+    Parse,
+    Resolve,
+    Lint,
+    Runtime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single error or warning, in a structured form that embedders can inspect.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub phase: Phase,
+    pub severity: Severity,
+    pub line: usize,
+    pub location: String,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub fn error(phase: Phase, line: usize, location: &str, message: &str) -> Self {
+        Self {
+            phase,
+            severity: Severity::Error,
+            line,
+            location: location.to_owned(),
+            message: message.to_owned(),
+            span: None,
+        }
+    }
+
+    pub fn warning(phase: Phase, line: usize, location: &str, message: &str) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(phase, line, location, message)
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// The 1-based column, in characters, where the span starts, if the span lies in `source`.
+    pub fn column(&self, source: &str) -> Option<usize> {
+        let before = source.get(..self.span?.start)?;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Some(before[line_start..].chars().count() + 1)
+    }
+
+    /// The source line containing the start of the span, with carets under the spanned text. This
+    /// is `None` if the span doesn't lie in `source`, which is a sign that it was given the wrong
+    /// source.
+    pub fn snippet(&self, source: &str) -> Option<String> {
+        let span = self.span?;
+        let before = source.get(..span.start)?;
+        let spanned = source.get(span.start..span.start + span.len)?;
+
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[span.start..]
+            .find('\n')
+            .map_or(source.len(), |i| span.start + i);
+        let line = source[line_start..line_end].trim_end_matches('\r');
+
+        // Columns are counted in characters, and the carets stop at the end of the line
+        let indent = before[line_start..].chars().count();
+        let width = spanned.split('\n').next().unwrap_or_default();
+        let width = width.chars().count().max(1);
+
+        Some(format!(
+            "    {line}\n    {}{}",
+            " ".repeat(indent),
+            "^".repeat(width)
+        ))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        };
+        write!(
+            f,
+            "[line {}] {severity}{}: {}",
+            self.line, self.location, self.message
+        )
+    }
+}
+
+/// A sink for the errors and warnings produced while running a program, so that the policy for
+/// surfacing them is decided by the host rather than the scanner.
+pub trait Diagnostics {
+    fn report(&mut self, diagnostic: Diagnostic);
+
+    /// Called with the code about to be run, which the spans of subsequent diagnostics refer to.
+    fn set_source(&mut self, _source: &str) {}
+
+    /// Whether an error (not just a warning) has been reported since the last `clear`.
+    fn had_error(&self) -> bool;
+
+    fn clear(&mut self);
+}
+
+/// Renders a diagnostic as the line that introduces it, given the source it was found in.
+pub type DiagnosticFormat = dyn Fn(&Diagnostic, &str) -> String;
+
+/// Prints each diagnostic to stderr as it is reported, followed by the offending source line when
+/// it is known. This is what the CLI uses.
+#[derive(Default)]
+pub struct PrintDiagnostics {
+    had_error: bool,
+    source: String,
+    /// How to render each diagnostic, if not with its `Display` implementation
+    format: Option<Box<DiagnosticFormat>>,
+}
+
+impl PrintDiagnostics {
+    /// Renders diagnostics with `format` instead, so that they can follow the host's conventions,
+    /// e.g. GCC's `file:line:column: error: message`.
+    pub fn with_format(mut self, format: impl Fn(&Diagnostic, &str) -> String + 'static) -> Self {
+        self.format = Some(Box::new(format));
+        self
+    }
+}
+
+impl fmt::Debug for PrintDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrintDiagnostics")
+            .field("had_error", &self.had_error)
+            .field("source", &self.source)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Diagnostics for PrintDiagnostics {
+    fn report(&mut self, diagnostic: Diagnostic) {
+        match &self.format {
+            Some(format) => eprintln!("{}", format(&diagnostic, &self.source)),
+            None => eprintln!("{diagnostic}"),
+        }
+        if let Some(snippet) = diagnostic.snippet(&self.source) {
+            eprintln!("{snippet}");
+        }
+        self.had_error |= diagnostic.severity == Severity::Error;
+    }
+
+    fn set_source(&mut self, source: &str) {
+        self.source = source.to_owned();
+    }
+
+    fn had_error(&self) -> bool {
+        self.had_error
+    }
+
+    fn clear(&mut self) {
+        self.had_error = false;
+    }
+}
+
+/// Silently collects every diagnostic, for embedders and editors.
+#[derive(Debug, Default)]
+pub struct CollectDiagnostics {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics for CollectDiagnostics {
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn had_error(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    fn clear(&mut self) {
+        self.diagnostics.clear();
+    }
+}
+
+/// An iterator over the characters of the source with lookahead, like `Peekable<Chars>`, but
+/// which also exposes the unscanned remainder of the source so that runs of characters can be
+/// consumed at once.
+#[derive(Debug, Clone)]
+struct Cursor<'source> {
+    chars: Chars<'source>,
+}
+
+impl<'source> Cursor<'source> {
+    fn new(source: &'source str) -> Self {
+        Self {
+            chars: source.chars(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.clone().next()
+    }
+
+    /// The character after the one returned by `peek`.
+    fn peek_next(&self) -> Option<char> {
+        self.chars.clone().nth(1)
+    }
+
+    fn next_if(&mut self, func: impl FnOnce(&char) -> bool) -> Option<char> {
+        let c = self.peek().filter(func)?;
+        self.chars.next();
+        Some(c)
+    }
+
+    fn next_if_eq(&mut self, expected: &char) -> Option<char> {
+        self.next_if(|c| c == expected)
+    }
+
+    fn rest(&self) -> &'source str {
+        self.chars.as_str()
+    }
+
+    /// Skips over the next `bytes` bytes of the source, which must end on a character boundary.
+    fn advance(&mut self, bytes: usize) {
+        self.chars = self.chars.as_str()[bytes..].chars();
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        self.chars.next()
+    }
+}
+
+/// The lexical rules that vary between Lox dialects: keywords, identifier characters and comment
+/// syntax. Everything else (operators, literals, whitespace) is fixed by the scanner.
+pub trait Dialect: fmt::Debug {
+    /// The keyword spelled `lexeme`, if any.
+    fn keyword(&self, lexeme: &str) -> Option<TokenKind>;
+
+    /// Any Unicode letter or `_`, so that names like `café` and `π` are identifiers.
+    fn is_identifier_start(&self, c: char) -> bool {
+        c.is_alphabetic() || c == '_'
+    }
+
+    fn is_identifier_continue(&self, c: char) -> bool {
+        c.is_alphanumeric() || c == '_'
+    }
+
+    /// Whether `c`, followed by `next`, starts a comment running to the end of the line.
+    fn starts_line_comment(&self, c: char, next: Option<char>) -> bool {
+        c == '/' && next == Some('/')
+    }
+}
+
+/// Lox as described in the book, optionally also accepting `#` line comments for Lox embedded in
+/// config files.
+#[derive(Debug, Default)]
+pub struct StandardDialect {
+    pub hash_comments: bool,
+}
+
+impl Dialect for StandardDialect {
+    fn keyword(&self, lexeme: &str) -> Option<TokenKind> {
+        use TokenKind as TK;
+
+        let kind = match lexeme {
+            "and" => TK::And,
+            "break" => TK::Break,
+            "class" => TK::Class,
+            "continue" => TK::Continue,
+            "else" => TK::Else,
+            "false" => TK::False,
+            "fun" => TK::Fun,
+            "for" => TK::For,
+            "if" => TK::If,
+            "nil" => TK::Nil,
+            "or" => TK::Or,
+            "print" => TK::Print,
+            "return" => TK::Return,
+            "super" => TK::Super,
+            "this" => TK::This,
+            "true" => TK::True,
+            "var" => TK::Var,
+            "while" => TK::While,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    fn starts_line_comment(&self, c: char, next: Option<char>) -> bool {
+        (c == '/' && next == Some('/')) || (self.hash_comments && c == '#')
+    }
+}
+
+#[derive(Debug)]
+pub struct Scanner<'source> {
+    source: &'source str,
+    tokens: Vec<Token>,
+    line: usize,
+    lexeme: String,
+    dialect: Box<dyn Dialect>,
+    /// Byte offset and column of the start of the current token
+    start: usize,
+    column: usize,
+}
+
+impl<'source> Scanner<'source> {
+    pub fn new(source: &'source str) -> Self {
+        Self {
+            source,
+            tokens: vec![],
+            line: 1,
+            lexeme: String::new(),
+            dialect: Box::new(StandardDialect::default()),
+            start: 0,
+            column: 1,
+        }
+    }
+
+    pub fn with_dialect(mut self, dialect: Box<dyn Dialect>) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Scans only the source from byte offset `start`, which must be the start of a line. The
+    /// tokens' offsets still count from the start of the whole source, so that code appended to
+    /// code that has already run (as at the REPL) can be scanned without invalidating the spans
+    /// already handed out.
+    pub fn starting_at(mut self, start: usize) -> Self {
+        self.start = start;
+        self
+    }
+
+    pub fn scan(&mut self, diagnostics: &mut dyn Diagnostics) -> &[Token] {
+        let scan_start = self.start;
+        let mut char_iter = Cursor::new(&self.source[self.start..]);
+
+        use TokenKind as TK;
+
+        loop {
+            self.begin_token(self.source.len() - char_iter.rest().len());
+
+            let Some(c) = char_iter.next() else {
+                break;
+            };
+            self.lexeme.push(c);
+
+            let p = char_iter.peek();
+
+            match c {
+                c if self.dialect.starts_line_comment(c, p) => {
+                    // The rest of the line is a comment so skip to the end, leaving the newline
+                    // for the main loop
+                    while char_iter.next_if(|&c_next| c_next != '\n').is_some() {}
+
+                    // We have to clear the lexeme string here manually because the comment isn't
+                    // treated as a token, but we've already added its first character to the
+                    // string. Would it be better to treat it as a token and simply ignore it
+                    // later?
+                    self.lexeme.clear();
+                }
+                '(' => self.add_token(TK::LeftParen),
+                ')' => self.add_token(TK::RightParen),
+                '{' => self.add_token(TK::LeftBrace),
+                '}' => self.add_token(TK::RightBrace),
+                ',' => self.add_token(TK::Comma),
+                '.' => self.add_token(TK::Dot),
+                '-' => self.add_token(TK::Minus),
+                '+' => self.add_token(TK::Plus),
+                ';' => self.add_token(TK::Semicolon),
+                '*' => self.add_token(TK::Star),
+                '!' => {
+                    if let Some(c_next @ '=') = p {
+                        self.lexeme.push(c_next);
+                        self.add_token(TK::BangEqual);
+                        char_iter.next();
+                    } else {
+                        self.add_token(TK::Bang)
+                    }
+                }
+                '=' => {
+                    if let Some(c_next @ '=') = p {
+                        self.lexeme.push(c_next);
+                        self.add_token(TK::EqualEqual);
+                        char_iter.next();
+                    } else {
+                        self.add_token(TK::Equal)
+                    }
+                }
+                '<' if char_iter.clone().take(2).eq(['<', '<']) => {
+                    self.scan_heredoc(&mut char_iter, diagnostics);
+                }
+                '<' => {
+                    if let Some(c_next @ '=') = p {
+                        self.lexeme.push(c_next);
+                        self.add_token(TK::LessEqual);
+                        char_iter.next();
+                    } else {
+                        self.add_token(TK::Less)
+                    }
+                }
+                '>' => {
+                    if let Some(c_next @ '=') = p {
+                        self.lexeme.push(c_next);
+                        self.add_token(TK::GreaterEqual);
+                        char_iter.next();
+                    } else {
+                        self.add_token(TK::Greater)
+                    }
+                }
+                '/' if p == Some('*') => self.skip_block_comment(&mut char_iter, diagnostics),
+                '/' => self.add_token(TK::Slash),
+                '"' => {
+                    // The literal is everything up to the next unescaped quote, and can be copied
+                    // in one go rather than character by character unless it contains escapes
+                    let rest = char_iter.rest();
+
+                    if let Some(end) = Self::string_end(rest) {
+                        let raw = &rest[..end];
+                        // Errors in the literal are reported where it starts, like its span
+                        let start_line = self.line;
+                        self.line += raw.matches('\n').count();
+                        self.lexeme.push_str(raw);
+                        self.lexeme.push('"');
+
+                        let lit = if raw.contains('\\') {
+                            Self::unescape(raw).unwrap_or_else(|message| {
+                                let span = Span {
+                                    start: self.start,
+                                    len: self.lexeme.len(),
+                                };
+                                diagnostics.report(
+                                    Diagnostic::error(Phase::Scan, start_line, "", message)
+                                        .with_span(span),
+                                );
+                                raw.to_owned()
+                            })
+                        } else {
+                            raw.to_owned()
+                        };
+                        self.add_token(TK::Literal(Literal::String(lit)));
+
+                        // Skip over the literal and the closing quote
+                        char_iter.advance(end + 1);
+                    } else {
+                        // Reported where the string starts, since that's where the missing quote
+                        // belongs rather than at the end of the file
+                        let start_line = self.line;
+                        self.line += rest.matches('\n').count();
+                        let quote = Span {
+                            start: self.start,
+                            len: 1,
+                        };
+                        diagnostics.report(
+                            Diagnostic::error(Phase::Scan, start_line, "", "Unterminated string.")
+                                .with_span(quote),
+                        );
+                        self.lexeme.clear();
+                        char_iter.advance(rest.len());
+                    }
+                }
+                '0'..='9' => {
+                    // Digits in the integer part
+                    while let Some(c_next @ '0'..='9') = char_iter.peek() {
+                        self.lexeme.push(c_next);
+                        char_iter.next();
+                    }
+
+                    // The '.' only belongs to the number if a digit follows it, so `123.` and
+                    // `123.method()` leave it to be scanned as a `Dot`
+                    if let (Some(c_next @ '.'), Some('0'..='9')) =
+                        (char_iter.peek(), char_iter.peek_next())
+                    {
+                        self.lexeme.push(c_next);
+                        char_iter.next();
+
+                        // Digits in the fractional part
+                        while let Some(c_next @ '0'..='9') = char_iter.peek() {
+                            self.lexeme.push(c_next);
+                            char_iter.next();
+                        }
+                    }
+                    let lit: f64 = self.lexeme.parse().unwrap_or_else(|_| {
+                        panic!("error parsing number literal: `{}`", self.lexeme)
+                    });
+                    let raw = self.lexeme.clone();
+                    self.add_token(TK::Literal(Literal::Number { value: lit, raw }));
+                }
+                c if self.dialect.is_identifier_start(c) => {
+                    while let Some(c_next) =
+                        char_iter.next_if(|&c_next| self.dialect.is_identifier_continue(c_next))
+                    {
+                        self.lexeme.push(c_next);
+                    }
+
+                    let token_kind = self
+                        .dialect
+                        .keyword(&self.lexeme)
+                        .unwrap_or_else(|| TK::Literal(Literal::Identifier(self.lexeme.clone())));
+                    self.add_token(token_kind);
+                }
+                '\\' => {
+                    // A trailing backslash continues the logical line onto the next physical one
+                    char_iter.next_if_eq(&'\r');
+                    if char_iter.next_if_eq(&'\n').is_some() {
+                        self.line += 1;
+                    } else {
+                        let backslash = Span {
+                            start: self.start,
+                            len: 1,
+                        };
+                        diagnostics.report(
+                            Diagnostic::error(
+                                Phase::Scan,
+                                self.line,
+                                "",
+                                "Expected a newline after '\\'.",
+                            )
+                            .with_span(backslash),
+                        );
+                    }
+                    self.lexeme.clear();
+                }
+                ' ' | '\r' | '\t' => {
+                    self.lexeme.clear();
+                }
+                '\n' => {
+                    self.line += 1;
+                    self.lexeme.clear();
+                }
+                _ => {
+                    let span = Span {
+                        start: self.start,
+                        len: c.len_utf8(),
+                    };
+                    diagnostics.report(
+                        Diagnostic::error(
+                            Phase::Scan,
+                            self.line,
+                            "",
+                            &format!("unexpected character `{c}`"),
+                        )
+                        .with_span(span),
+                    );
+                    self.lexeme.clear();
+                }
+            }
+        }
+
+        // The end is at the end of the last line with anything on it rather than after any
+        // trailing newlines, so that an error "at end" points at the line left unfinished
+        let end = scan_start
+            + self.source[scan_start..]
+                .trim_end_matches(&[' ', '\r', '\t', '\n'][..])
+                .len();
+        let line = self.line - self.source[end..].matches('\n').count();
+        let line_start = self.source[..end].rfind('\n').map_or(0, |i| i + 1);
+        let column = self.source[line_start..end].chars().count() + 1;
+        self.tokens.push(Token::new(TK::Eof, "", line, column, end));
+
+        &self.tokens
+    }
+
+    /// The byte offset of the quote closing a string literal whose body starts `rest`, skipping
+    /// over escaped quotes.
+    fn string_end(rest: &str) -> Option<usize> {
+        let mut chars = rest.char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Some(i),
+                // Whatever follows a backslash can't close the string
+                '\\' => {
+                    chars.next();
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    /// Decodes the escape sequences in the body of a string literal: `\n`, `\t`, `\r`, `\"` and
+    /// `\\`, `\xNN` for a character up to `\x7F`, and `\u{N...}` for any Unicode scalar value given
+    /// in 1 to 6 hex digits.
+    fn unescape(raw: &str) -> Result<String, &'static str> {
+        let mut lit = String::with_capacity(raw.len());
+        let mut chars = raw.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('\\', Some('x')) => {
+                    chars.next();
+                    let digits: String = chars.by_ref().take(2).collect();
+                    let code = Some(&digits)
+                        .filter(|digits| digits.len() == 2 && Self::is_hex(digits))
+                        .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                        .filter(|&code| code <= 0x7f)
+                        .ok_or("Invalid hex escape.")?;
+                    lit.push(char::from(code));
+                }
+                ('\\', Some('u')) => {
+                    chars.next();
+                    if chars.next() != Some('{') {
+                        return Err("Invalid unicode escape.");
+                    }
+                    let digits: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                    let c = Some(&digits)
+                        .filter(|digits| (1..=6).contains(&digits.len()) && Self::is_hex(digits))
+                        .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                        .and_then(char::from_u32)
+                        .ok_or("Invalid unicode escape.")?;
+                    lit.push(c);
+                }
+                ('\\', Some(&escaped)) => {
+                    chars.next();
+                    lit.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '"' => '"',
+                        '\\' => '\\',
+                        _ => return Err("Invalid escape sequence."),
+                    });
+                }
+                _ => lit.push(c),
+            }
+        }
+
+        Ok(lit)
+    }
+
+    /// Whether `digits` is made up only of hex digits. `from_str_radix` alone would also accept a
+    /// leading sign.
+    fn is_hex(digits: &str) -> bool {
+        digits.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    /// Skips a `/* ... */` comment, which may contain nested block comments. The opening `/` has
+    /// already been consumed.
+    fn skip_block_comment(&mut self, char_iter: &mut Cursor, diagnostics: &mut dyn Diagnostics) {
+        let start_line = self.line;
+
+        // The opening `*`
+        char_iter.next();
+        let mut depth = 1;
+
+        while depth > 0 {
+            match char_iter.next() {
+                Some('/') if char_iter.next_if_eq(&'*').is_some() => depth += 1,
+                Some('*') if char_iter.next_if_eq(&'/').is_some() => depth -= 1,
+                Some('\n') => self.line += 1,
+                Some(_) => {}
+                None => {
+                    diagnostics.report(Diagnostic::error(
+                        Phase::Scan,
+                        start_line,
+                        "",
+                        "Unterminated block comment.",
+                    ));
+                    break;
+                }
+            }
+        }
+
+        self.lexeme.clear();
+    }
+
+    /// Scans a heredoc string `<<<END ... END`, whose body runs from the line after the opening
+    /// delimiter up to (but not including the newline before) the line holding just the closing
+    /// delimiter. That line may indent the delimiter, and may follow it with the `;` ending the
+    /// statement, as in `END;`, but nothing else. With `<<<~END` the common leading whitespace of
+    /// the body lines is also stripped. The first `<` has already been consumed.
+    fn scan_heredoc(&mut self, char_iter: &mut Cursor, diagnostics: &mut dyn Diagnostics) {
+        let start_line = self.line;
+
+        // The remaining two `<` of the opener
+        for _ in 0..2 {
+            self.lexeme.extend(char_iter.next());
+        }
+
+        let squiggly = char_iter.next_if_eq(&'~').is_some();
+        if squiggly {
+            self.lexeme.push('~');
+        }
+
+        let mut delimiter = String::new();
+        while let Some(c_next) = char_iter.next_if(|&c| c.is_ascii_alphanumeric() || c == '_') {
+            delimiter.push(c_next);
+        }
+        self.lexeme.push_str(&delimiter);
+
+        char_iter.next_if_eq(&'\r');
+        if delimiter.is_empty() || char_iter.next_if_eq(&'\n').is_none() {
+            diagnostics.report(Diagnostic::error(
+                Phase::Scan,
+                start_line,
+                "",
+                "Expected a delimiter and a newline after '<<<'.",
+            ));
+            self.lexeme.clear();
+            return;
+        }
+        self.lexeme.push('\n');
+        self.line += 1;
+
+        let mut lines = vec![];
+        loop {
+            if char_iter.peek().is_none() {
+                diagnostics.report(Diagnostic::error(
+                    Phase::Scan,
+                    start_line,
+                    "",
+                    "Unterminated heredoc.",
+                ));
+                self.lexeme.clear();
+                return;
+            }
+
+            // The closing delimiter may be followed by the `;` ending the statement, so only the
+            // delimiter itself is consumed here
+            let mut lookahead = char_iter.clone();
+            let indent = lookahead
+                .clone()
+                .take_while(|&c| c == ' ' || c == '\t')
+                .count();
+            let is_terminator = lookahead
+                .by_ref()
+                .skip(indent)
+                .take(delimiter.chars().count())
+                .eq(delimiter.chars())
+                && matches!(
+                    lookahead.rest().lines().next().map(str::trim),
+                    None | Some("" | ";")
+                );
+
+            if is_terminator {
+                for _ in 0..indent + delimiter.chars().count() {
+                    self.lexeme.extend(char_iter.next());
+                }
+                break;
+            }
+
+            let mut line = String::new();
+            while let Some(c_next) = char_iter.next_if(|&c| c != '\n') {
+                line.push(c_next);
+            }
+            self.lexeme.push_str(&line);
+
+            if char_iter.next().is_some() {
+                self.lexeme.push('\n');
+                self.line += 1;
+            }
+            lines.push(line);
+        }
+
+        if squiggly {
+            let indent = lines
+                .iter()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.len() - line.trim_start().len())
+                .min()
+                .unwrap_or(0);
+
+            for line in &mut lines {
+                // Blank lines may be shorter than the common indentation
+                let strip = indent.min(line.len() - line.trim_start().len());
+                line.drain(..strip);
+            }
+        }
+
+        self.add_token(TokenKind::Literal(Literal::String(lines.join("\n"))));
+    }
+
+    /// Records that the next token starts at byte offset `start`, working out its column from
+    /// the text skipped since the previous token.
+    fn begin_token(&mut self, start: usize) {
+        let skipped = &self.source[self.start..start];
+
+        self.column = match skipped.rfind('\n') {
+            Some(i) => skipped[i + 1..].chars().count() + 1,
+            None => self.column + skipped.chars().count(),
+        };
+        self.start = start;
+    }
+
+    fn add_token(&mut self, kind: TokenKind) {
+        self.tokens.push(Token::new(
+            kind,
+            &self.lexeme,
+            self.line,
+            self.column,
+            self.start,
+        ));
+        self.lexeme.clear();
+    }
+}
+
+/// A byte range in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Span {
+    /// The span from the start of this one to the end of `end`.
+    pub fn to(self, end: Span) -> Span {
+        Span {
+            start: self.start,
+            len: end.start + end.len - self.start,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub lexeme: String,
+    pub line: usize,
+    /// 1-based column of the token's first character, counted in characters
+    pub column: usize,
+    /// Byte offset of the token's first character in the source
+    pub start: usize,
+    /// Length of the token's lexeme in bytes
+    pub len: usize,
+}
+
+impl Token {
+    pub fn new(
+        kind: TokenKind,
+        lexeme: impl AsRef<str>,
+        line: usize,
+        column: usize,
+        start: usize,
+    ) -> Self {
+        let lexeme = lexeme.as_ref().to_owned();
+        Self {
+            kind,
+            len: lexeme.len(),
+            lexeme,
+            line,
+            column,
+            start,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.start,
+            len: self.len,
+        }
+    }
+}
+
+/// The book's `KIND lexeme literal` format, where only strings and numbers have a literal.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.lexeme)?;
+
+        match &self.kind {
+            TokenKind::Literal(literal @ (Literal::String(_) | Literal::Number { .. })) => {
+                write!(f, " {literal}")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Summary counts for a source file, as reported by `jlox stats`.
+#[derive(Debug, Default)]
+struct Stats {
+    tokens_by_kind: BTreeMap<String, usize>,
+    code_lines: usize,
+    comment_lines: usize,
+    blank_lines: usize,
+    functions: usize,
+    classes: usize,
+    max_depth: usize,
+}
+
+impl Stats {
+    /// The counts for `source`, from both its tokens and the statements parsed from them.
+    fn new(source: &str, tokens: &[Token], statements: &[Stmt]) -> Self {
+        let mut stats = Self::default();
+        let mut code_lines = HashSet::new();
+        let mut depth = 0usize;
+
+        for token in tokens {
+            if token.kind == TokenKind::Eof {
+                continue;
+            }
+
+            // A multi-line token (e.g. a string) covers every line it spans
+            let first_line = token.line - token.lexeme.matches('\n').count();
+            code_lines.extend(first_line..=token.line);
+
+            *stats
+                .tokens_by_kind
+                .entry(token.kind.to_string())
+                .or_default() += 1;
+
+            match token.kind {
+                TokenKind::LeftBrace => {
+                    depth += 1;
+                    stats.max_depth = stats.max_depth.max(depth);
+                }
+                TokenKind::RightBrace => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        // Declarations are counted from the syntax tree, so that nested ones are found wherever
+        // they are. Lox has no classes yet, so a script that parses declares none.
+        for statement in statements {
+            lint::visit_functions(statement, &mut |_| stats.functions += 1);
+        }
+
+        // Lines without any tokens are either blank or only contain comments
+        for (i, line) in source.lines().enumerate() {
+            if code_lines.contains(&(i + 1)) {
+                stats.code_lines += 1;
+            } else if line.trim().is_empty() {
+                stats.blank_lines += 1;
+            } else {
+                stats.comment_lines += 1;
+            }
+        }
+
+        stats
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total: usize = self.tokens_by_kind.values().sum();
+        writeln!(f, "tokens: {total}")?;
+        for (kind, count) in &self.tokens_by_kind {
+            writeln!(f, "  {kind}: {count}")?;
+        }
+        writeln!(
+            f,
+            "lines: {} code, {} comment, {} blank",
+            self.code_lines, self.comment_lines, self.blank_lines
+        )?;
+        writeln!(f, "functions: {}", self.functions)?;
+        writeln!(f, "classes: {}", self.classes)?;
+        write!(f, "max nesting depth: {}", self.max_depth)
+    }
+}
+
+/// How running a program went, for the caller to act on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    Ok,
+    /// The program failed to scan or parse, so none of it ran
+    StaticError,
+    RuntimeError,
+    /// Whatever was reading the output stopped (e.g. `head` in a pipeline), so the rest of the
+    /// program was skipped. This isn't reported as an error, since it isn't the program's fault.
+    OutputClosed,
+}
+
+impl RunOutcome {
+    /// The process exit code for this outcome, following the reference jlox.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            RunOutcome::Ok => 0,
+            RunOutcome::StaticError => 65,
+            RunOutcome::RuntimeError => 70,
+            // What a shell reports for a process killed by SIGPIPE, like other Unix tools
+            RunOutcome::OutputClosed => 141,
+        }
+    }
+}
+
+pub struct Lox<D: Diagnostics = PrintDiagnostics> {
+    /// Whether to also accept `#` line comments
+    pub hash_comments: bool,
+    pub diagnostics: D,
+    /// Whether arithmetic producing NaN or an infinity is a runtime error
+    pub strict_numeric: bool,
+    /// Whether to report how long each phase of a run took
+    pub timings: bool,
+    /// Whether to warn about suspicious code before running it
+    pub lint: bool,
+    /// The flags that `//#if` directives in scripts test for
+    pub defines: HashSet<String>,
+    /// How many Lox calls may be in progress at once
+    pub max_call_depth: usize,
+    /// How many bytes of native stack those calls may use
+    pub max_stack_size: usize,
+    /// The most arguments a call, or parameters a function, may have
+    pub max_args: usize,
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        Self::with_diagnostics(PrintDiagnostics::default())
+    }
+
+    /// Prints diagnostics rendered by `format`, rather than as `[line N] Error: message`.
+    pub fn with_error_format(format: impl Fn(&Diagnostic, &str) -> String + 'static) -> Self {
+        Self::with_diagnostics(PrintDiagnostics::default().with_format(format))
+    }
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: Diagnostics> Lox<D> {
+    pub fn with_diagnostics(diagnostics: D) -> Self {
+        Self {
+            hash_comments: false,
+            diagnostics,
+            strict_numeric: false,
+            timings: false,
+            lint: false,
+            defines: HashSet::new(),
+            max_call_depth: interpreter::DEFAULT_MAX_CALL_DEPTH,
+            max_stack_size: interpreter::DEFAULT_MAX_STACK_SIZE,
+            max_args: parser::DEFAULT_MAX_ARGS,
+        }
+    }
+
+    pub fn interpreter(&self) -> Interpreter {
+        Interpreter::new()
+            .with_strict_numeric(self.strict_numeric)
+            .with_max_call_depth(self.max_call_depth)
+            .with_max_stack_size(self.max_stack_size)
+    }
+
+    /// Runs `code`, forgetting the diagnostics of any earlier run, so that one `Lox` can run
+    /// program after program.
+    pub fn run(&mut self, code: &str, interpreter: &mut Interpreter) -> RunOutcome {
+        self.diagnostics.clear();
+        self.run_from(code, 0, interpreter)
+    }
+
+    /// Runs the code in `source` from byte offset `from` on, for when the code before it has
+    /// already been run but may still be referred to by diagnostics.
+    fn run_from(&mut self, source: &str, from: usize, interpreter: &mut Interpreter) -> RunOutcome {
+        let start = Instant::now();
+        let mut scanner = Scanner::new(source)
+            .with_dialect(self.dialect())
+            .starting_at(from);
+        self.diagnostics.set_source(source);
+        let tokens = scanner.scan(&mut self.diagnostics);
+        self.report_timing("scan", start);
+
+        let start = Instant::now();
+        let parsed = self.parser(tokens).parse_program();
+        self.report_timing("parse", start);
+
+        let statements = match parsed {
+            Ok(statements) => statements,
+            Err(errors) => {
+                for error in &errors {
+                    self.diagnostics.report(error.into());
+                }
+                return RunOutcome::StaticError;
+            }
+        };
+
+        // Don't run a program that failed to scan
+        if self.diagnostics.had_error() {
+            return RunOutcome::StaticError;
+        }
+
+        let start = Instant::now();
+        let resolved = Resolver::new().resolve(&statements);
+        self.report_timing("resolve", start);
+
+        if let Err(errors) = resolved {
+            for error in &errors {
+                self.diagnostics.report(error.into());
+            }
+            return RunOutcome::StaticError;
+        }
+
+        if self.lint {
+            for warning in lint::missing_returns(&statements) {
+                self.diagnostics.report(warning);
+            }
+        }
+
+        let start = Instant::now();
+        let result = interpreter.interpret(&statements);
+        self.report_timing("interpret", start);
+
+        match result {
+            Ok(()) => RunOutcome::Ok,
+            Err(error) if error.output_error == Some(io::ErrorKind::BrokenPipe) => {
+                RunOutcome::OutputClosed
+            }
+            Err(error) => {
+                self.diagnostics.report((&error).into());
+                RunOutcome::RuntimeError
+            }
+        }
+    }
+
+    /// Prints how long a phase that began at `start` took, if timings were asked for. This goes to
+    /// stderr so that it doesn't mix with the program's own output.
+    fn report_timing(&self, phase: &str, start: Instant) {
+        if self.timings {
+            eprintln!("{phase:>9}: {:?}", start.elapsed());
+        }
+    }
+
+    /// Writes `output` to stdout, as the commands that inspect a script rather than run it do. If
+    /// whatever was reading stdout has stopped, that is an `OutputClosed` outcome, not an error.
+    fn write_output(output: &str) -> Result<RunOutcome> {
+        match io::stdout().write_all(output.as_bytes()) {
+            Ok(()) => Ok(RunOutcome::Ok),
+            Err(error) if error.kind() == io::ErrorKind::BrokenPipe => Ok(RunOutcome::OutputClosed),
+            Err(error) => Err(error).context("Could not write output"),
+        }
+    }
+
+    /// Reads a script, ready to be scanned. Diagnostics of any earlier run are forgotten, since
+    /// those from preprocessing the script are the first of this one.
+    fn read_script(&mut self, filename: &str) -> Result<String> {
+        self.diagnostics.clear();
+        let code = std::fs::read_to_string(filename).context("Could not read code from file")?;
+        Ok(self.preprocess(Self::strip_preamble(&code)))
+    }
+
+    pub fn run_file(&mut self, filename: &str) -> Result<RunOutcome> {
+        let code = self.read_script(filename)?;
+        Ok(self.run_from(&code, 0, &mut self.interpreter()))
+    }
+
+    /// Prints the tokens of a script, one per line, instead of running it.
+    pub fn dump_tokens(&mut self, filename: &str) -> Result<RunOutcome> {
+        let code = self.read_script(filename)?;
+
+        let mut scanner = Scanner::new(&code).with_dialect(self.dialect());
+        self.diagnostics.set_source(&code);
+        let mut output = String::new();
+        for token in scanner.scan(&mut self.diagnostics) {
+            output.push_str(&format!("{token}\n"));
+        }
+
+        let outcome = Self::write_output(&output)?;
+        Ok(if self.diagnostics.had_error() {
+            RunOutcome::StaticError
+        } else {
+            outcome
+        })
+    }
+
+    /// Prints the syntax tree of a script instead of running it.
+    pub fn dump_ast(&mut self, filename: &str) -> Result<RunOutcome> {
+        let code = self.read_script(filename)?;
+
+        let mut scanner = Scanner::new(&code).with_dialect(self.dialect());
+        self.diagnostics.set_source(&code);
+        let tokens = scanner.scan(&mut self.diagnostics);
+
+        match self.parser(tokens).parse_program() {
+            Ok(statements) if !self.diagnostics.had_error() => {
+                Self::write_output(&ast_printer::print_program(&statements))
+            }
+            Ok(_) => Ok(RunOutcome::StaticError),
+            Err(errors) => {
+                for error in &errors {
+                    self.diagnostics.report(error.into());
+                }
+                Ok(RunOutcome::StaticError)
+            }
+        }
+    }
+
+    /// Blanks out the regions between `//#if FLAG` and `//#endif` lines whose flag isn't defined.
+    /// Regions can nest, and each blanked line keeps its newline so that line numbers in errors
+    /// still match the file.
+    fn preprocess(&mut self, code: &str) -> String {
+        let mut output = String::with_capacity(code.len());
+        // For each enclosing `//#if`, its line and whether its region is included
+        let mut regions: Vec<(usize, bool)> = vec![];
+
+        for (i, line) in code.split_inclusive('\n').enumerate() {
+            let directive = line.trim();
+            let active = regions.iter().all(|&(_, included)| included);
+
+            if let Some(flag) = directive.strip_prefix("//#if ") {
+                regions.push((i + 1, self.defines.contains(flag.trim())));
+            } else if directive == "//#endif" {
+                if regions.pop().is_none() {
+                    self.diagnostics.report(Diagnostic::error(
+                        Phase::Scan,
+                        i + 1,
+                        "",
+                        "'//#endif' without a matching '//#if'.",
+                    ));
+                }
+            } else if !active {
+                if line.ends_with('\n') {
+                    output.push('\n');
+                }
+                continue;
+            }
+
+            output.push_str(line);
+        }
+
+        for (line, _) in regions {
+            self.diagnostics.report(Diagnostic::error(
+                Phase::Scan,
+                line,
+                "",
+                "'//#if' without a matching '//#endif'.",
+            ));
+        }
+
+        output
+    }
+
+    fn parser<'tokens>(&self, tokens: &'tokens [Token]) -> Parser<'tokens> {
+        Parser::new(tokens).with_max_args(self.max_args)
+    }
+
+    fn dialect(&self) -> Box<dyn Dialect> {
+        Box::new(StandardDialect {
+            hash_comments: self.hash_comments,
+        })
+    }
+
+    pub fn stats_file(&mut self, filename: &str) -> Result<RunOutcome> {
+        let code = self.read_script(filename)?;
+
+        let mut scanner = Scanner::new(&code).with_dialect(self.dialect());
+        self.diagnostics.set_source(&code);
+        let tokens = scanner.scan(&mut self.diagnostics);
+
+        let statements = match self.parser(tokens).parse_program() {
+            Ok(statements) => statements,
+            Err(errors) => {
+                for error in &errors {
+                    self.diagnostics.report(error.into());
+                }
+                return Ok(RunOutcome::StaticError);
+            }
+        };
+        if self.diagnostics.had_error() {
+            return Ok(RunOutcome::StaticError);
+        }
+
+        Self::write_output(&format!("{}\n", Stats::new(&code, tokens, &statements)))
+    }
+
+    /// Strips a leading UTF-8 BOM and `#!` line so scripts can be made directly executable. The
+    /// shebang's newline is kept so that line numbers in errors still match the file.
+    fn strip_preamble(code: &str) -> &str {
+        let code = code.strip_prefix('\u{feff}').unwrap_or(code);
+
+        match code.strip_prefix("#!") {
+            Some(rest) => &rest[rest.find('\n').unwrap_or(rest.len())..],
+            None => code,
+        }
+    }
+
+    /// Runs the last line typed at the REPL, which starts at byte offset `line_start` of the
+    /// session's `history`. A line that is a bare expression, with no trailing `;`, is evaluated
+    /// and its value printed; anything else is run as statements.
+    fn run_line(
+        &mut self,
+        history: &str,
+        line_start: usize,
+        interpreter: &mut Interpreter,
+    ) -> Result<()> {
+        // Try the line as an expression quietly, so that statements don't report spurious errors
+        let mut diagnostics = CollectDiagnostics::default();
+        let mut scanner = Scanner::new(history)
+            .with_dialect(self.dialect())
+            .starting_at(line_start);
+        let tokens = scanner.scan(&mut diagnostics);
+
+        if !diagnostics.had_error() {
+            if let Ok(expr) = self.parser(tokens).parse() {
+                self.diagnostics.set_source(history);
+                match interpreter.evaluate(&expr) {
+                    Ok(value) => writeln!(io::stdout(), "{value}")?,
+                    Err(error) => self.diagnostics.report((&error).into()),
+                }
+                return Ok(());
+            }
+        }
+
+        // Errors have already been reported, and the REPL carries on regardless
+        self.run_from(history, line_start, interpreter);
+        Ok(())
+    }
+
+    pub fn run_prompt(&mut self) -> Result<()> {
+        let mut line = String::new();
+        let input = io::stdin();
+        // Shared by every line, so that variables persist between them
+        let mut interpreter = self.interpreter();
+        // Every line run so far, which functions declared on earlier lines have spans into
+        let mut history = String::new();
+
+        loop {
+            print!("> ");
+            // annoying but apparently we have to manually flush stdout to get the prompt to reliably
+            // appear here
+            io::stdout().flush()?;
+
+            input.read_line(&mut line)?;
+
+            if line.is_empty() {
+                break;
+            }
+
+            if line == "\n" {
+                line.clear();
+                continue;
+            }
+
+            let line_start = history.len();
+            history.push_str(&line);
+            self.run_line(&history, line_start, &mut interpreter)?;
+            self.diagnostics.clear();
+
+            line.clear();
+        }
+
+        Ok(())
+    }
+}
+
+/// The tokens of `code`, which must scan without errors. For the tests of every module.
+#[cfg(test)]
+fn tokens_of(code: &str) -> Vec<Token> {
+    let mut diagnostics = CollectDiagnostics::default();
+    let tokens = Scanner::new(code).scan(&mut diagnostics).to_vec();
+    assert_eq!(diagnostics.diagnostics, [], "scanning {code:?}");
+    tokens
+}
+
+/// The statements of `code`, which must scan and parse without errors. For the tests of every
+/// module.
+#[cfg(test)]
+fn statements_of(code: &str) -> Vec<Stmt> {
+    Parser::new(&tokens_of(code)).parse_program().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use TokenKind as TK;
+
+    /// Scans `code` in `dialect`, returning the tokens before the `Eof` and any diagnostics.
+    fn scan_in(dialect: impl Dialect + 'static, code: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        let mut diagnostics = CollectDiagnostics::default();
+        let mut scanner = Scanner::new(code).with_dialect(Box::new(dialect));
+        let mut tokens = scanner.scan(&mut diagnostics).to_vec();
+
+        assert_eq!(tokens.pop().map(|token| token.kind), Some(TK::Eof));
+        (tokens, diagnostics.diagnostics)
+    }
+
+    /// The kinds of the tokens in `code`, which must scan without errors.
+    fn kinds(code: &str) -> Vec<TokenKind> {
+        let (tokens, diagnostics) = scan_in(StandardDialect::default(), code);
+        assert_eq!(diagnostics, [], "scanning {code:?}");
+        tokens.into_iter().map(|token| token.kind).collect()
+    }
+
+    /// The messages of the diagnostics from scanning `code`.
+    fn scan_errors(code: &str) -> Vec<String> {
+        let (_, diagnostics) = scan_in(StandardDialect::default(), code);
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    fn number(raw: &str) -> TokenKind {
+        TK::Literal(Literal::Number {
+            value: raw.parse().unwrap(),
+            raw: raw.to_owned(),
+        })
+    }
+
+    fn string(s: &str) -> TokenKind {
+        TK::Literal(Literal::String(s.to_owned()))
+    }
+
+    fn identifier(name: &str) -> TokenKind {
+        TK::Literal(Literal::Identifier(name.to_owned()))
+    }
+
+    #[test]
+    fn strip_preamble_removes_a_bom() {
+        assert_eq!(<Lox>::strip_preamble("\u{feff}print 1;"), "print 1;");
+    }
+
+    #[test]
+    fn strip_preamble_removes_a_shebang_but_keeps_its_line() {
+        let code = "#!/usr/bin/env jlox\nprint 1;";
+        assert_eq!(<Lox>::strip_preamble(code), "\nprint 1;");
+        assert_eq!(
+            <Lox>::strip_preamble(&format!("\u{feff}{code}")),
+            "\nprint 1;"
+        );
+    }
+
+    #[test]
+    fn strip_preamble_leaves_other_code_alone() {
+        assert_eq!(<Lox>::strip_preamble("print 1;\n#!"), "print 1;\n#!");
+    }
+
+    #[test]
+    fn hash_comments_are_skipped_when_enabled() {
+        let dialect = StandardDialect {
+            hash_comments: true,
+        };
+        let (tokens, diagnostics) = scan_in(dialect, "1 # note\n2");
+        let kinds: Vec<_> = tokens.into_iter().map(|token| token.kind).collect();
+
+        assert_eq!(kinds, [number("1"), number("2")]);
+        assert_eq!(diagnostics, []);
+    }
+
+    #[test]
+    fn hash_is_an_unexpected_character_by_default() {
+        assert_eq!(scan_errors("1 # note"), ["unexpected character `#`"]);
+    }
+
+    #[test]
+    fn heredoc() {
+        let code = "print <<<END\nline one\n  \"two\"\\n\nEND;";
+        assert_eq!(
+            kinds(code),
+            [TK::Print, string("line one\n  \"two\"\\n"), TK::Semicolon]
+        );
+    }
+
+    #[test]
+    fn squiggly_heredoc_strips_common_indentation() {
+        let code = "<<<~SQL\n    select *\n\n      from t\n    SQL";
+        assert_eq!(kinds(code), [string("select *\n\n  from t")]);
+    }
+
+    #[test]
+    fn heredoc_ends_only_at_a_line_holding_just_the_delimiter() {
+        let code = "<<<END\nENDING\nEND x\n  END\n";
+        assert_eq!(kinds(code), [string("ENDING\nEND x")]);
+    }
+
+    #[test]
+    fn unterminated_heredoc() {
+        assert_eq!(scan_errors("<<<END\ntext\n"), ["Unterminated heredoc."]);
+    }
+
+    #[test]
+    fn minus_signs_are_always_separate_tokens() {
+        let cases = [
+            ("1-2", vec![number("1"), TK::Minus, number("2")]),
+            ("--5", vec![TK::Minus, TK::Minus, number("5")]),
+            ("- 5", vec![TK::Minus, number("5")]),
+            (
+                "1 - -2",
+                vec![number("1"), TK::Minus, TK::Minus, number("2")],
+            ),
+        ];
+
+        for (code, expected) in cases {
+            assert_eq!(kinds(code), expected, "scanning {code:?}");
+        }
+    }
+
+    #[test]
+    fn backslash_continues_a_line() {
+        let (tokens, diagnostics) = scan_in(StandardDialect::default(), "1 +\\\n  2 \\\r\n* 3");
+        let kinds: Vec<_> = tokens.iter().map(|token| token.kind.clone()).collect();
+        let lines: Vec<_> = tokens.iter().map(|token| token.line).collect();
+
+        assert_eq!(diagnostics, []);
+        assert_eq!(
+            kinds,
+            [number("1"), TK::Plus, number("2"), TK::Star, number("3")]
+        );
+        // Errors still refer to the physical lines
+        assert_eq!(lines, [1, 1, 2, 3, 3]);
+    }
+
+    #[test]
+    fn backslash_must_end_its_line() {
+        let (_, diagnostics) = scan_in(StandardDialect::default(), "1 \\ + 2");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Expected a newline after '\\'.");
+        assert_eq!(diagnostics[0].span, Some(Span { start: 2, len: 1 }));
+    }
+
+    /// Standard Lox, except that functions are declared with `func`.
+    #[derive(Debug)]
+    struct FuncDialect;
+
+    impl Dialect for FuncDialect {
+        fn keyword(&self, lexeme: &str) -> Option<TokenKind> {
+            match lexeme {
+                "func" => Some(TK::Fun),
+                "fun" => None,
+                _ => StandardDialect::default().keyword(lexeme),
+            }
+        }
+    }
+
+    #[test]
+    fn custom_dialect_keywords() {
+        let (tokens, diagnostics) = scan_in(FuncDialect, "func fun() { return; }");
+        let kinds: Vec<_> = tokens.into_iter().map(|token| token.kind).collect();
+
+        assert_eq!(diagnostics, []);
+        assert_eq!(
+            kinds,
+            [
+                TK::Fun,
+                identifier("fun"),
+                TK::LeftParen,
+                TK::RightParen,
+                TK::LeftBrace,
+                TK::Return,
+                TK::Semicolon,
+                TK::RightBrace,
+            ]
+        );
+    }
+
+    /// Decodes a string literal's body one character at a time, as the scanner used to.
+    fn naive_string(body: &str) -> String {
+        let mut lit = String::new();
+        let mut chars = body.chars();
+        while let Some(c) = chars.next() {
+            lit.push(match c {
+                '\\' => match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some(escaped) => escaped,
+                    None => unreachable!("the body can't end in a lone backslash"),
+                },
+                c => c,
+            });
+        }
+        lit
+    }
+
+    #[test]
+    fn strings_match_the_naive_scan() {
+        let bodies = [
+            "",
+            "plain",
+            "two\nlines",
+            "é and 😀",
+            r#"escaped \"quotes\""#,
+            r"tab\tnewline\nreturn\rbackslash\\",
+        ];
+
+        for body in bodies {
+            let code = format!("\"{body}\" x");
+            let (tokens, diagnostics) = scan_in(StandardDialect::default(), &code);
+
+            assert_eq!(diagnostics, [], "scanning {code:?}");
+            assert_eq!(tokens[0].kind, string(&naive_string(body)));
+            assert_eq!(tokens[0].lexeme, format!("\"{body}\""));
+            // The token after the string is on the line the string ends on
+            assert_eq!(tokens[1].line, 1 + body.matches('\n').count());
+        }
+    }
+
+    /// Times scanning a file full of long string literals, the case that copying a literal's
+    /// whole run at once is for, and checks the literals against decoding them a character at a
+    /// time. Run it with `cargo test --release string_scanning_benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn string_scanning_benchmark() {
+        const STRINGS: usize = 20_000;
+
+        // Mostly plain strings, with every tenth one full of escapes
+        let plain = "long string literal ".repeat(50);
+        let escaped = r"escaped\tstring\n".repeat(50);
+        let bodies: Vec<_> = (0..STRINGS)
+            .map(|i| if i % 10 == 0 { &escaped } else { &plain })
+            .collect();
+        let code: String = bodies
+            .iter()
+            .enumerate()
+            .map(|(i, body)| format!("var s{i} = \"{body}\";\n"))
+            .collect();
+
+        let mut diagnostics = CollectDiagnostics::default();
+        let mut scanner = Scanner::new(&code);
+        let start = Instant::now();
+        let tokens = scanner.scan(&mut diagnostics);
+        let elapsed = start.elapsed();
+
+        assert_eq!(diagnostics.diagnostics, []);
+        assert_eq!(tokens.len(), 5 * STRINGS + 1);
+        for (i, body) in bodies.iter().enumerate() {
+            assert_eq!(tokens[5 * i + 3].kind, string(&naive_string(body)));
+        }
+
+        let megabytes = code.len() as f64 / 1e6;
+        println!(
+            "scanned {megabytes:.1} MB of strings in {elapsed:?} ({:.0} MB/s)",
+            megabytes / elapsed.as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn dot_is_only_part_of_a_number_when_digits_follow() {
+        let cases = [
+            ("123.", vec![number("123"), TK::Dot]),
+            ("123.456", vec![number("123.456")]),
+            (
+                "123.method()",
+                vec![
+                    number("123"),
+                    TK::Dot,
+                    identifier("method"),
+                    TK::LeftParen,
+                    TK::RightParen,
+                ],
+            ),
+        ];
+
+        for (code, expected) in cases {
+            assert_eq!(kinds(code), expected, "scanning {code:?}");
+        }
+    }
+
+    #[test]
+    fn block_comments_are_skipped() {
+        assert_eq!(kinds("1 /* one */ 2"), [number("1"), number("2")]);
+        assert_eq!(
+            kinds("1 /* outer /* inner */ still outer */ 2"),
+            [number("1"), number("2")]
+        );
+    }
+
+    #[test]
+    fn block_comments_count_lines() {
+        let (tokens, _) = scan_in(StandardDialect::default(), "/* a\nb\n*/ 1");
+        assert_eq!(tokens[0].line, 3);
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let (_, diagnostics) = scan_in(StandardDialect::default(), "1\n/* a /* b */\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Unterminated block comment.");
+        assert_eq!(diagnostics[0].line, 2);
+    }
+
+    #[test]
+    fn keywords() {
+        let keywords = [
+            ("and", TK::And),
+            ("break", TK::Break),
+            ("class", TK::Class),
+            ("continue", TK::Continue),
+            ("else", TK::Else),
+            ("false", TK::False),
+            ("fun", TK::Fun),
+            ("for", TK::For),
+            ("if", TK::If),
+            ("nil", TK::Nil),
+            ("or", TK::Or),
+            ("print", TK::Print),
+            ("return", TK::Return),
+            ("super", TK::Super),
+            ("this", TK::This),
+            ("true", TK::True),
+            ("var", TK::Var),
+            ("while", TK::While),
+        ];
+
+        for (lexeme, kind) in keywords {
+            assert_eq!(kinds(lexeme), [kind], "scanning {lexeme:?}");
+        }
+    }
+
+    #[test]
+    fn names_starting_with_keywords_are_identifiers() {
+        for name in [
+            "orchid", "android", "classy", "format", "iffy", "variable", "_if", "For",
+        ] {
+            assert_eq!(kinds(name), [identifier(name)], "scanning {name:?}");
+        }
+    }
+
+    #[test]
+    fn token_columns_and_offsets() {
+        let (tokens, _) = scan_in(
+            StandardDialect::default(),
+            "var answer = 42;\n  print \"é\" + x;",
+        );
+        let positions: Vec<_> = tokens
+            .iter()
+            .map(|token| (token.line, token.column, token.start, token.len))
+            .collect();
+
+        assert_eq!(
+            positions,
+            [
+                (1, 1, 0, 3),
+                (1, 5, 4, 6),
+                (1, 12, 11, 1),
+                (1, 14, 13, 2),
+                (1, 16, 15, 1),
+                (2, 3, 19, 5),
+                (2, 9, 25, 4),
+                // Columns count characters, so the two-byte `é` only moves them on by one
+                (2, 13, 30, 1),
+                (2, 15, 32, 1),
+                (2, 16, 33, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn diagnostic_column_and_snippet() {
+        let source = "var a = 1;\nprint a + @;";
+        let (_, diagnostics) = scan_in(StandardDialect::default(), source);
+
+        assert_eq!(diagnostics[0].column(source), Some(11));
+        assert_eq!(
+            diagnostics[0].snippet(source).unwrap(),
+            "    print a + @;\n              ^"
+        );
+    }
+
+    #[test]
+    fn end_of_file_is_at_the_end_of_the_last_line() {
+        let source = "print 1 +\n\n  \n";
+        let mut lox = Lox::with_diagnostics(CollectDiagnostics::default());
+        lox.run(source, &mut lox.interpreter());
+
+        let diagnostic = &lox.diagnostics.diagnostics[0];
+        assert_eq!(
+            diagnostic.to_string(),
+            "[line 1] Error at end: Expect expression."
+        );
+        assert_eq!(diagnostic.column(source), Some(10));
+        assert_eq!(
+            diagnostic.snippet(source).unwrap(),
+            "    print 1 +\n             ^"
+        );
+    }
+
+    #[test]
+    fn end_of_scanning_from_an_offset_is_not_before_the_offset() {
+        let source = "print 1;\n\n";
+        let mut scanner = Scanner::new(source).starting_at(9);
+        let tokens = scanner.scan(&mut CollectDiagnostics::default());
+
+        assert_eq!(tokens.len(), 1);
+        assert_eq!((tokens[0].start, tokens[0].line), (9, 1));
+    }
+
+    #[test]
+    fn diagnostic_outside_the_source_has_no_snippet() {
+        let diagnostic = Diagnostic::error(Phase::Runtime, 1, "", "message")
+            .with_span(Span { start: 40, len: 1 });
+
+        assert_eq!(diagnostic.column("print 1;"), None);
+        assert_eq!(diagnostic.snippet("print 1;"), None);
+    }
+
+    #[test]
+    fn scanning_from_an_offset_keeps_offsets_into_the_whole_source() {
+        let mut diagnostics = CollectDiagnostics::default();
+        let mut scanner = Scanner::new("print 1;\nprint 2;").starting_at(9);
+        let positions: Vec<_> = scanner
+            .scan(&mut diagnostics)
+            .iter()
+            .map(|token| (token.column, token.start))
+            .collect();
+
+        assert_eq!(positions, [(1, 9), (7, 15), (8, 16), (9, 17)]);
+    }
+
+    #[test]
+    fn hex_and_unicode_escapes() {
+        assert_eq!(kinds(r#""\x41\x7f""#), [string("A\x7f")]);
+        assert_eq!(kinds(r#""\u{1F600} \u{e9}""#), [string("😀 é")]);
+    }
+
+    #[test]
+    fn invalid_hex_and_unicode_escapes() {
+        let cases = [
+            (r#""\x4""#, "Invalid hex escape."),
+            (r#""\x80""#, "Invalid hex escape."),
+            (r#""\x+1""#, "Invalid hex escape."),
+            (r#""\u{D800}""#, "Invalid unicode escape."),
+            (r#""\u{110000}""#, "Invalid unicode escape."),
+            (r#""\u{}""#, "Invalid unicode escape."),
+            (r#""\u41""#, "Invalid unicode escape."),
+        ];
+
+        for (code, message) in cases {
+            assert_eq!(scan_errors(code), [message], "scanning {code}");
+        }
+    }
+
+    #[test]
+    fn number_literals_display_as_their_value() {
+        let literal = |raw: &str| Literal::Number {
+            value: raw.parse().unwrap(),
+            raw: raw.to_owned(),
+        };
+        assert_eq!(literal("5.0").to_string(), "5");
+        assert_eq!(literal("5.5").to_string(), "5.5");
+    }
+
+    #[test]
+    fn escapes() {
+        let cases = [
+            (r#""a\nb""#, "a\nb"),
+            (r#""a\tb""#, "a\tb"),
+            (r#""a\rb""#, "a\rb"),
+            (r#""say \"hi\"""#, "say \"hi\""),
+            (r#""back\\slash""#, "back\\slash"),
+        ];
+
+        for (code, lit) in cases {
+            assert_eq!(kinds(code), [string(lit)], "scanning {code}");
+        }
+    }
+
+    #[test]
+    fn escaped_newlines_are_not_counted_as_lines() {
+        let (tokens, _) = scan_in(StandardDialect::default(), "\"a\\nb\" x");
+        assert_eq!(tokens[1].line, 1);
+    }
+
+    #[test]
+    fn invalid_escape() {
+        let (_, diagnostics) = scan_in(StandardDialect::default(), "\n\"one\ntwo\\q\"");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Invalid escape sequence.");
+        // Reported where the string starts, not where the escape is
+        assert_eq!(diagnostics[0].line, 2);
+    }
+
+    #[test]
+    fn unterminated_string_is_reported_at_its_opening_quote() {
+        let (tokens, diagnostics) =
+            scan_in(StandardDialect::default(), "print 1;\nprint \"one\ntwo");
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Unterminated string.");
+        assert_eq!(diagnostics[0].line, 2);
+        assert_eq!(diagnostics[0].span, Some(Span { start: 15, len: 1 }));
+    }
+
+    const CONDITIONAL: &str = "print 1;\n//#if DEBUG\nprint 2;\n//#endif\nprint @;";
+
+    /// A `Lox` that collects its diagnostics, with `defines` defined.
+    fn lox_with(defines: &[&str]) -> Lox<CollectDiagnostics> {
+        let mut lox = Lox::with_diagnostics(CollectDiagnostics::default());
+        lox.defines = defines.iter().map(|flag| flag.to_string()).collect();
+        lox
+    }
+
+    #[test]
+    fn conditional_region_is_included_when_its_flag_is_defined() {
+        let mut lox = lox_with(&["DEBUG"]);
+        assert_eq!(
+            lox.preprocess(CONDITIONAL),
+            "print 1;\n//#if DEBUG\nprint 2;\n//#endif\nprint @;"
+        );
+    }
+
+    #[test]
+    fn conditional_region_is_blanked_otherwise() {
+        let mut lox = lox_with(&["OTHER"]);
+        let code = lox.preprocess(CONDITIONAL);
+        assert_eq!(code, "print 1;\n//#if DEBUG\n\n//#endif\nprint @;");
+
+        // Lines after the region keep their numbers
+        lox.run(&code, &mut lox.interpreter());
+        assert_eq!(lox.diagnostics.diagnostics[0].line, 5);
+    }
+
+    #[test]
+    fn conditional_regions_nest() {
+        let code = "//#if A\na\n//#if B\nb\n//#endif\n//#endif\nc";
+        assert_eq!(
+            lox_with(&["B"]).preprocess(code),
+            "//#if A\n\n//#if B\n\n//#endif\n//#endif\nc"
+        );
+        assert_eq!(lox_with(&["A"]).preprocess(code).lines().nth(1), Some("a"));
+    }
+
+    #[test]
+    fn unbalanced_directives() {
+        let mut lox = lox_with(&[]);
+        lox.preprocess("//#if A\n//#endif\n//#endif\n//#if B\n");
+        let errors: Vec<_> = lox
+            .diagnostics
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.message.as_str()))
+            .collect();
+
+        assert_eq!(
+            errors,
+            [
+                (3, "'//#endif' without a matching '//#if'."),
+                (4, "'//#if' without a matching '//#endif'."),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokens_display_in_the_books_format() {
+        let (tokens, _) = scan_in(StandardDialect::default(), "while >= 5.0 \"hi\" name");
+        let displayed: Vec<_> = tokens.iter().map(Token::to_string).collect();
+
+        assert_eq!(
+            displayed,
+            [
+                "WHILE while",
+                "GREATER_EQUAL >=",
+                "NUMBER 5.0 5",
+                "STRING \"hi\" hi",
+                "IDENTIFIER name",
+            ]
+        );
+    }
+
+    #[test]
+    fn unicode_identifiers_and_strings() {
+        assert_eq!(
+            kinds("var café = \"🎉 ok\"; π_2"),
+            [
+                TK::Var,
+                identifier("café"),
+                TK::Equal,
+                string("🎉 ok"),
+                TK::Semicolon,
+                identifier("π_2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn emoji_is_not_an_identifier() {
+        assert_eq!(scan_errors("🎉"), ["unexpected character `🎉`"]);
+    }
+
+    /// The stats of `code`, which must scan and parse without errors.
+    fn stats_of(code: &str) -> Stats {
+        Stats::new(code, &tokens_of(code), &statements_of(code))
+    }
+
+    #[test]
+    fn stats_for_a_small_program() {
+        let code = "// Adds things\nfun add(a, b) {\n  { return a + b; }\n}\n\n/* done */\nprint add(1, 2);\n";
+        let stats = stats_of(code);
+
+        assert_eq!(stats.tokens_by_kind.values().sum::<usize>(), 24);
+        assert_eq!(stats.tokens_by_kind["IDENTIFIER"], 6);
+        assert_eq!(stats.tokens_by_kind["NUMBER"], 2);
+        assert_eq!(stats.tokens_by_kind["LEFT_BRACE"], 2);
+        assert_eq!(
+            (stats.code_lines, stats.comment_lines, stats.blank_lines),
+            (4, 2, 1)
+        );
+        assert_eq!((stats.functions, stats.classes), (1, 0));
+        assert_eq!(stats.max_depth, 2);
+    }
+
+    #[test]
+    fn stats_count_nested_functions() {
+        let code =
+            "fun outer() {\n  fun inner() {}\n  if (true) { fun again() {} }\n}\nprint \"fun\";";
+        assert_eq!(stats_of(code).functions, 3);
+    }
+
+    #[test]
+    fn stats_count_a_trailing_comment_as_a_comment() {
+        let stats = stats_of("print 1;\n// the end\n");
+
+        assert_eq!(
+            (stats.code_lines, stats.comment_lines, stats.blank_lines),
+            (1, 1, 0)
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Optional warnings about legal but suspicious code, reported with `jlox --lint`.
+
+use crate::parser::{FunctionDecl, Stmt};
+use crate::{Diagnostic, Phase};
+
+/// Warns about every function in `statements`, including nested ones, that returns a value on
+/// some paths but can also reach the end of its body and so implicitly return `nil`.
+pub fn missing_returns(statements: &[Stmt]) -> Vec<Diagnostic> {
+    let mut warnings = vec![];
+    for statement in statements {
+        visit_functions(statement, &mut |function| {
+            let has_value_return = function.body.iter().any(returns_value);
+            let can_fall_off = !function.body.iter().any(always_returns);
+
+            if has_value_return && can_fall_off {
+                let name = &function.name;
+                let message = format!(
+                    "Function '{}' returns a value on some paths but can reach the end of its body.",
+                    name.lexeme
+                );
+                let location = format!(" at '{}'", name.lexeme);
+                warnings.push(
+                    Diagnostic::warning(Phase::Lint, name.line, &location, &message)
+                        .with_span(name.span()),
+                );
+            }
+        });
+    }
+    warnings
+}
+
+/// Calls `f` on each function declared in `statement`, however deeply nested.
+pub(crate) fn visit_functions(statement: &Stmt, f: &mut impl FnMut(&FunctionDecl)) {
+    match statement {
+        Stmt::Function(function) => {
+            f(function);
+            for statement in &function.body {
+                visit_functions(statement, f);
+            }
+        }
+        Stmt::Block(statements) => {
+            for statement in statements {
+                visit_functions(statement, f);
+            }
+        }
+        Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            visit_functions(then_branch, f);
+            if let Some(else_branch) = else_branch {
+                visit_functions(else_branch, f);
+            }
+        }
+        Stmt::While { body, .. } => visit_functions(body, f),
+        _ => {}
+    }
+}
+
+/// Whether any `return` in `statement`, outside of nested functions, has a value.
+fn returns_value(statement: &Stmt) -> bool {
+    match statement {
+        Stmt::Return { value, .. } => value.is_some(),
+        Stmt::Block(statements) => statements.iter().any(returns_value),
+        Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => returns_value(then_branch) || else_branch.as_deref().is_some_and(returns_value),
+        Stmt::While { body, .. } => returns_value(body),
+        _ => false,
+    }
+}
+
+/// Whether every path through `statement` ends in a `return`. Loops are assumed to be able to
+/// finish, since whether they do depends on their condition.
+fn always_returns(statement: &Stmt) -> bool {
+    match statement {
+        Stmt::Return { .. } => true,
+        Stmt::Block(statements) => statements.iter().any(always_returns),
+        Stmt::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => always_returns(then_branch) && always_returns(else_branch),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{statements_of, Severity};
+
+    /// The warnings for `code`, which must scan and parse.
+    fn warnings(code: &str) -> Vec<Diagnostic> {
+        missing_returns(&statements_of(code))
+    }
+
+    #[test]
+    fn missing_return_on_the_else_path() {
+        let warnings = warnings("fun sign(n) {\n  if (n > 0) return 1;\n  else print n;\n}");
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, Severity::Warning);
+        assert_eq!(warnings[0].line, 1);
+        assert_eq!(
+            warnings[0].message,
+            "Function 'sign' returns a value on some paths but can reach the end of its body."
+        );
+    }
+
+    #[test]
+    fn no_warning_when_every_path_returns() {
+        let code = "fun sign(n) {\n  if (n > 0) return 1;\n  else { return -1; }\n}";
+        assert_eq!(warnings(code), []);
+    }
+
+    #[test]
+    fn no_warning_when_no_path_returns_a_value() {
+        assert_eq!(warnings("fun f(n) { if (n) return; print n; }"), []);
+    }
+
+    #[test]
+    fn nested_functions_are_checked_separately() {
+        let code = "fun outer() {\n  fun inner() { while (true) return 1; }\n  return inner;\n}";
+        let names: Vec<_> = warnings(code)
+            .iter()
+            .map(|warning| warning.location.clone())
+            .collect();
+        assert_eq!(names, [" at 'inner'"]);
+    }
+}
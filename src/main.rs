@@ -1,372 +1,124 @@
-#![allow(unused)]
+use jlox_rs::{test_runner, Lox, RunOutcome};
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 
-use std::io;
-use std::io::prelude::*;
+use std::backtrace::Backtrace;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
 
-#[derive(Debug)]
-enum Literal {
-    Identifier,
-    String(String),
-    Number(f64),
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} [--hash-comments] [--strict-numeric] [--timings] [--lint] [--define FLAG]... [--max-call-depth N] [--tokens | --ast] [--debug] [stats | test] [script]"
+    );
+    std::process::exit(64);
 }
 
-#[derive(Debug)]
-enum TokenKind {
-    // Single-character tokens
-    LeftParen,
-    RightParen,
-    LeftBrace,
-    RightBrace,
-    Comma,
-    Dot,
-    Minus,
-    Plus,
-    Semicolon,
-    Slash,
-    Star,
-    // One or two character tokens
-    Bang,
-    BangEqual,
-    Equal,
-    EqualEqual,
-    Greater,
-    GreaterEqual,
-    Less,
-    LessEqual,
-    // Literals
-    Literal(Literal),
-    // Keywords
-    And,
-    Class,
-    Else,
-    False,
-    Fun,
-    For,
-    If,
-    Nil,
-    Or,
-    Print,
-    Return,
-    Super,
-    This,
-    True,
-    Var,
-    While,
-    // End-of-file
-    Eof,
+/// Exits the process with the outcome's exit code, unless it succeeded.
+fn exit_on_error(outcome: RunOutcome) {
+    if outcome != RunOutcome::Ok {
+        std::process::exit(outcome.exit_code());
+    }
 }
 
-// TODO: this is awful - replace with proper error handling
-static mut HAD_ERROR: bool = false;
+/// The native stack size of the thread that runs jlox. The interpreter recurses for each Lox call,
+/// so the default main thread stack would run out well before
+/// [`jlox_rs::interpreter::DEFAULT_MAX_CALL_DEPTH`] calls, especially in debug builds.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
 
-#[derive(Debug)]
-struct Scanner<'source> {
-    source: &'source str,
-    tokens: Vec<Token>,
-    line: usize,
-    lexeme: String,
+fn main() -> Result<()> {
+    // Run on a thread of our own, since the main thread's stack size can't be chosen
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_cli)?
+        .join()
+        .unwrap_or_else(|payload| panic::resume_unwind(payload))
 }
 
-impl<'source> Scanner<'source> {
-    fn new(source: &'source str) -> Self {
-        Self {
-            source,
-            tokens: vec![],
-            line: 1,
-            lexeme: String::new(),
-        }
-    }
-
-    fn scan(&mut self) -> &[Token] {
-        let mut char_iter = self.source.chars().peekable();
-
-        use TokenKind as TK;
-
-        while let Some(c) = char_iter.next() {
-            self.lexeme.push(c);
-
-            let p = char_iter.peek();
-
-            match c {
-                '(' => self.add_token(TK::LeftParen),
-                ')' => self.add_token(TK::RightParen),
-                '{' => self.add_token(TK::LeftBrace),
-                '}' => self.add_token(TK::RightBrace),
-                ',' => self.add_token(TK::Comma),
-                '.' => self.add_token(TK::Dot),
-                '-' => self.add_token(TK::Minus),
-                '+' => self.add_token(TK::Plus),
-                ';' => self.add_token(TK::Semicolon),
-                '*' => self.add_token(TK::Star),
-                '!' => {
-                    if let Some(&c_next @ '=') = p {
-                        self.lexeme.push(c_next);
-                        self.add_token(TK::BangEqual);
-                        char_iter.next();
-                    } else {
-                        self.add_token(TK::Bang)
-                    }
-                }
-                '=' => {
-                    if let Some(&c_next @ '=') = p {
-                        self.lexeme.push(c_next);
-                        self.add_token(TK::EqualEqual);
-                        char_iter.next();
-                    } else {
-                        self.add_token(TK::Equal)
-                    }
-                }
-                '<' => {
-                    if let Some(&c_next @ '=') = p {
-                        self.lexeme.push(c_next);
-                        self.add_token(TK::LessEqual);
-                        char_iter.next();
-                    } else {
-                        self.add_token(TK::Less)
-                    }
-                }
-                '>' => {
-                    if let Some(&c_next @ '=') = p {
-                        self.lexeme.push(c_next);
-                        self.add_token(TK::GreaterEqual);
-                        char_iter.next();
-                    } else {
-                        self.add_token(TK::Greater)
-                    }
-                }
-                '/' => {
-                    if let Some('/') = p {
-                        // Don't skip over the second slash - let loop below start at the second
-                        // second slash so (which we know exists at this point) so we can peek to
-                        // the next character.
-
-                        // The rest of the line is a comment so now skip to the end
-                        while let Some(c_comment) = char_iter.next() {
-                            if let Some('\n') = char_iter.peek() {
-                                break;
-                            }
-                        }
-
-                        // We have to clear the lexeme string here manually because the double
-                        // slash isn't treated as a token, but we've already added the first slash
-                        // to the string. Would it be better to treat it as a token and simply
-                        // ignore it later?
-                        self.lexeme.clear();
-                    } else {
-                        self.add_token(TK::Slash);
-                    }
-                }
-                '"' => {
-                    let mut lit = String::new();
-
-                    while let Some(&c_next) = char_iter.peek() {
-                        match c_next {
-                            '"' => {
-                                self.lexeme.push(c_next);
-                                self.add_token(TK::Literal(Literal::String(lit)));
-                                break;
-                            }
-                            '\n' => {
-                                self.lexeme.push(c_next);
-                                lit.push(c_next);
-                                self.line += 1;
-                            }
-                            _ => {
-                                self.lexeme.push(c_next);
-                                lit.push(c_next);
-                            }
-                        }
-                        char_iter.next();
-
-                        // Check if we reached the end before finding a closing quote
-                        if char_iter.peek().is_none() {
-                            Lox::report(self.line, "", "Unterminated string.");
-                        }
-                    }
-
-                    // Skip over the closing quote
-                    char_iter.next();
-                }
-                '0'..='9' => {
-                    // Digits in the integer part
-                    while let Some(&c_next @ '0'..='9') = char_iter.peek() {
-                        self.lexeme.push(c_next);
-                        char_iter.next();
-                    }
-
-                    if let Some(&c_next @ '.') = char_iter.peek() {
-                        // How can we disallow trailing '.' in number literals if we can't do 2
-                        // character lookahead? (`Peekable` only allows for 1 char lookahead via
-                        // peak() - we could switch to using `...chars().windows(3)` in the main
-                        // loop?). For now, assume the '.' is part of the number regardless of what
-                        // comes after it.
-                        self.lexeme.push(c_next);
-                        char_iter.next();
-
-                        // Digits in the fractional part
-                        while let Some(&c_next @ '0'..='9') = char_iter.peek() {
-                            self.lexeme.push(c_next);
-                            char_iter.next();
-                        }
-                    }
-                    let lit: f64 = self
-                        .lexeme
-                        .parse()
-                        .expect(&format!("error parsing number literal: `{}`", self.lexeme));
-                    self.add_token(TK::Literal(Literal::Number(lit)));
-                }
-                'a'..='z' | 'A'..='Z' | '_' => {
-                    while let Some(&c_next @ ('a'..='z' | 'A'..='Z' | '0'..='9')) = char_iter.peek() {
-                        self.lexeme.push(c_next);
-                        char_iter.next();
-                    }
-
-                    let token_kind = match self.lexeme.as_str() {
-                        "and" => TK::And,
-                        "class" => TK::Class,
-                        "else" => TK::Else,
-                        "false" => TK::False,
-                        "fun" => TK::Fun,
-                        "for" => TK::For,
-                        "if" => TK::If,
-                        "nil" => TK::Nil,
-                        "or" => TK::Or,
-                        "print" => TK::Print,
-                        "return" => TK::Return,
-                        "super" => TK::Super,
-                        "this" => TK::This,
-                        "true" => TK::True,
-                        "var" => TK::Var,
-                        "while" => TK::While,
-                        "eof" => TK::Eof,
-                        _ => TK::Literal(Literal::Identifier),
-                    };
-                    self.add_token(token_kind);
+fn run_cli() -> Result<()> {
+    let mut lox = Lox::new();
+    // Half of the stack leaves ample headroom for the frames outside of Lox calls
+    lox.max_stack_size = STACK_SIZE / 2;
+    let mut debug = false;
+    // Whether to print the script's tokens or syntax tree rather than run it
+    let mut dump: Option<fn(&mut Lox, &str) -> Result<RunOutcome>> = None;
+
+    let mut args: Vec<String> = vec![];
+    let mut all_args = std::env::args();
+
+    while let Some(arg) = all_args.next() {
+        match arg.as_str() {
+            "--hash-comments" => lox.hash_comments = true,
+            "--debug" => debug = true,
+            "--strict-numeric" => lox.strict_numeric = true,
+            "--timings" => lox.timings = true,
+            "--lint" => lox.lint = true,
+            "--tokens" => dump = Some(Lox::dump_tokens),
+            "--ast" => dump = Some(Lox::dump_ast),
+            "--define" => match all_args.next() {
+                Some(flag) => {
+                    lox.defines.insert(flag);
                 }
-                ' ' | '\r' | '\t' => {
-                    self.lexeme.clear();
+                None => {
+                    eprintln!("Option `--define` needs a flag name");
+                    usage(&args[0]);
                 }
-                '\n' => {
-                    self.line += 1;
-                    self.lexeme.clear();
+            },
+            "--max-call-depth" => match all_args.next().map(|depth| depth.parse()) {
+                Some(Ok(depth)) => lox.max_call_depth = depth,
+                _ => {
+                    eprintln!("Option `--max-call-depth` needs a number of calls");
+                    usage(&args[0]);
                 }
-                _ => Lox::report(self.line, "", &format!("unexpected character `{c}`")),
+            },
+            flag if flag.starts_with("--") => {
+                eprintln!("Unknown option `{flag}`");
+                usage(&args[0]);
             }
-        }
-
-        self.tokens.push(Token::new(TK::Eof, "", self.line));
-
-        &self.tokens
-    }
-
-    fn add_token(&mut self, kind: TokenKind) {
-        self.tokens.push(Token::new(kind, &self.lexeme, self.line));
-        self.lexeme.clear();
-    }
-}
-
-#[derive(Debug)]
-struct Token {
-    kind: TokenKind,
-    lexeme: String,
-    line: usize,
-}
-
-impl Token {
-    fn new(kind: TokenKind, lexeme: impl AsRef<str>, line: usize) -> Self {
-        Self {
-            kind,
-            lexeme: lexeme.as_ref().to_owned(),
-            line,
+            _ => args.push(arg),
         }
     }
 
-    fn to_string(&self) -> String {
-        // formatting of the literal
-        format!("{:?} {}", self.kind, self.lexeme)
+    // An internal panic is a bug in jlox rather than in the user's program, so report it as such
+    // instead of with a Rust backtrace (unless debugging jlox itself)
+    if debug {
+        panic::set_hook(Box::new(|info| {
+            eprintln!("{info}\n{}", Backtrace::force_capture());
+        }));
+    } else {
+        panic::set_hook(Box::new(|_| {}));
     }
-}
 
-struct Lox {}
-
-impl Lox {
-    fn new() -> Self {
-        Self {}
-    }
-
-    fn run(&mut self, code: &str) {
-        let mut scanner = Scanner::new(code);
-        let tokens = scanner.scan();
-
-        for token in tokens {
-            println!("{:?}", token);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match args.as_slice() {
+        // A deliberate internal error, so that tests can check how one is reported
+        _ if std::env::var_os("JLOX_TEST_PANIC").is_some() => {
+            panic!("panic injected by JLOX_TEST_PANIC")
         }
-    }
-
-    fn run_file(&mut self, filename: &str) -> Result<()> {
-        let code = std::fs::read_to_string(filename).context("Could not read code from file")?;
-        self.run(&code);
-
-        if unsafe { HAD_ERROR } {
-            std::process::exit(65);
-        }
-
-        Ok(())
-    }
-
-    fn run_prompt(&mut self) -> Result<()> {
-        let mut line = String::new();
-        let input = io::stdin();
-
-        loop {
-            print!("> ");
-            // annoying but apparently we have to manually flush stdout to get the prompt to reliably
-            // appear here
-            io::stdout().flush()?;
-
-            input.read_line(&mut line)?;
-
-            if line.is_empty() {
-                break;
+        [_, command, script] if command == "stats" => lox.stats_file(script).map(exit_on_error),
+        [_, command, dir] if command == "test" => {
+            if !test_runner::run(Path::new(dir))? {
+                std::process::exit(1);
             }
-
-            if line == "\n" {
-                line.clear();
-                continue;
-            }
-
-            self.run(&line);
-            unsafe { HAD_ERROR = false };
-
-            line.clear();
-        }
-
-        Ok(())
-    }
-
-    fn report(line: usize, loc: &str, msg: &str) {
-        println!("[line {line}] Error{loc}: {msg}");
-        unsafe {
-            HAD_ERROR = true;
+            Ok(())
         }
-    }
-}
-
-fn main() -> Result<()> {
-    let mut lox = Lox::new();
-
-    let args: Vec<_> = std::env::args().collect();
-
-    match args.len() {
-        2 => lox.run_file(&args[1])?,
-        n if n > 2 => {
-            eprintln!("Usage: {} [script]", args[0]);
-            std::process::exit(64);
+        [_, script] => match dump {
+            Some(dump) => dump(&mut lox, script).map(exit_on_error),
+            None => lox.run_file(script).map(exit_on_error),
+        },
+        [_] => lox.run_prompt(),
+        _ => usage(&args[0]),
+    }));
+
+    match result {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            eprintln!("internal error: {message}");
+            eprintln!("This is a bug in jlox-rs; please report it, including the script that triggered it. Run with --debug for a backtrace.");
+            std::process::exit(70);
         }
-        _ => lox.run_prompt()?,
     }
-
-    Ok(())
 }
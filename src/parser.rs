@@ -0,0 +1,1099 @@
+use crate::{Diagnostic, Literal, Phase, Span, Token, TokenKind};
+
+use std::cell::Cell;
+use std::mem;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiteralValue {
+    Number {
+        value: f64,
+        /// The literal as written in the source, carried over from the token
+        raw: String,
+    },
+    String(String),
+    Bool(bool),
+    Nil,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Binary {
+        left: Box<Expr>,
+        op: Token,
+        right: Box<Expr>,
+    },
+    Unary {
+        op: Token,
+        right: Box<Expr>,
+    },
+    Literal(LiteralValue),
+    Grouping(Box<Expr>),
+    Logical {
+        left: Box<Expr>,
+        op: Token,
+        right: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        /// The closing parenthesis, where errors in the call are reported
+        paren: Token,
+        args: Vec<Expr>,
+    },
+    Variable {
+        name: Token,
+        depth: Depth,
+    },
+    Assign {
+        name: Token,
+        value: Box<Expr>,
+        depth: Depth,
+    },
+}
+
+/// How many scopes out from where a variable is used it was declared, as worked out by the
+/// resolver. Unresolved variables, which includes every global, are `None`.
+pub type Depth = Cell<Option<usize>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Expression(Expr),
+    /// Prints its values on one line, separated by spaces
+    Print {
+        keyword: Token,
+        values: Vec<Expr>,
+    },
+    Var {
+        name: Token,
+        initializer: Option<Expr>,
+    },
+    Block(Vec<Stmt>),
+    If {
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+        /// A `for` loop's increment, run after the body even when it `continue`s
+        increment: Option<Expr>,
+    },
+    Break,
+    Continue,
+    /// A lone `;`, which does nothing
+    Empty,
+    /// Shared, so that function values created from it needn't copy the body
+    Function(Rc<FunctionDecl>),
+    Return {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDecl {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub token: Token,
+    pub message: String,
+    /// The source to underline, which is just `token` unless the error covers more
+    pub span: Span,
+}
+
+impl ParseError {
+    fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        let location = match error.token.kind {
+            TokenKind::Eof => " at end".to_owned(),
+            _ => format!(" at '{}'", error.token.lexeme),
+        };
+        Diagnostic::error(Phase::Parse, error.token.line, &location, &error.message)
+            .with_span(error.span)
+    }
+}
+
+/// The most arguments a call, or parameters a function, can have by default, matching the
+/// reference jlox.
+pub const DEFAULT_MAX_ARGS: usize = 255;
+
+/// A recursive-descent parser over the scanner's tokens, which must end with an `Eof` token.
+///
+/// Each grammar rule is a method, from lowest to highest precedence:
+///
+/// ```text
+/// program     → declaration* EOF ;
+/// declaration → funDecl | varDecl | statement ;
+/// funDecl     → "fun" function ;
+/// function    → IDENTIFIER "(" parameters? ")" block ;
+/// parameters  → IDENTIFIER ( "," IDENTIFIER )* ;
+/// varDecl     → "var" IDENTIFIER ( "=" expression )? ";" ;
+/// statement   → exprStmt | forStmt | ifStmt | printStmt | returnStmt | whileStmt
+///             | breakStmt | continueStmt | block | emptyStmt ;
+/// forStmt     → "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")"
+///               statement ;
+/// ifStmt      → "if" "(" expression ")" statement ( "else" statement )? ;
+/// whileStmt   → "while" "(" expression ")" statement ;
+/// breakStmt   → "break" ";" ;
+/// continueStmt → "continue" ";" ;
+/// block       → "{" declaration* "}" ;
+/// exprStmt    → expression ";" ;
+/// printStmt   → "print" expression ( "," expression )* ";" ;
+/// returnStmt  → "return" expression? ";" ;
+/// emptyStmt   → ";" ;
+/// expression  → assignment ;
+/// assignment  → IDENTIFIER "=" assignment | logic_or ;
+/// logic_or    → logic_and ( "or" logic_and )* ;
+/// logic_and   → equality ( "and" equality )* ;
+/// equality    → comparison ( ( "!=" | "==" ) comparison )* ;
+/// comparison  → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+/// term        → factor ( ( "-" | "+" ) factor )* ;
+/// factor      → unary ( ( "/" | "*" ) unary )* ;
+/// unary       → ( "!" | "-" ) unary | call ;
+/// call        → primary ( "(" arguments? ")" )* ;
+/// arguments   → expression ( "," expression )* ;
+/// primary     → NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")"
+///             | IDENTIFIER ;
+/// ```
+#[derive(Debug)]
+pub struct Parser<'tokens> {
+    tokens: &'tokens [Token],
+    current: usize,
+    /// How many loops enclose the statement being parsed, within the current function
+    loop_depth: usize,
+    /// The most arguments a call, or parameters a function, may have
+    max_args: usize,
+    /// Errors that don't leave the parser confused about where it is, so that it can carry on
+    /// without synchronizing
+    errors: Vec<ParseError>,
+}
+
+// A `ParseError` is as big as it is because it carries its whole token. Errors end a parse
+// quickly, so there's nothing to gain from boxing it.
+#[allow(clippy::result_large_err)]
+impl<'tokens> Parser<'tokens> {
+    pub fn new(tokens: &'tokens [Token]) -> Self {
+        Self {
+            tokens,
+            current: 0,
+            loop_depth: 0,
+            max_args: DEFAULT_MAX_ARGS,
+            errors: vec![],
+        }
+    }
+
+    /// Limits how many arguments a call, or parameters a function, may have. A backend that
+    /// encodes the count in an instruction's operand needs it to fit.
+    pub fn with_max_args(mut self, max_args: usize) -> Self {
+        self.max_args = max_args;
+        self
+    }
+
+    /// Parses a single expression, which must make up all of the tokens.
+    pub fn parse(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.expression()?;
+
+        if !self.is_at_end() {
+            return Err(self.error(self.peek(), "Expect end of expression."));
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses a whole program. After a syntax error the parser skips to the next statement and
+    /// carries on, so that every error in the program is returned rather than just the first.
+    pub fn parse_program(&mut self) -> Result<Vec<Stmt>, Vec<ParseError>> {
+        let mut statements = vec![];
+        let mut errors: Vec<ParseError> = vec![];
+
+        while !self.is_at_end() {
+            // A closing delimiter can't start a declaration, so at the top level it must be left
+            // over from a program that already ended
+            if self.check(&TokenKind::RightBrace) || self.check(&TokenKind::RightParen) {
+                let token = self.advance().clone();
+                // Unless the error that was just recovered from was already reported at it
+                let reported = errors
+                    .last()
+                    .is_some_and(|error| error.span == token.span());
+                if !reported {
+                    let message = format!("Unexpected '{}' after program.", token.lexeme);
+                    errors.push(self.error(&token, &message));
+                }
+                continue;
+            }
+
+            let declaration = self.declaration();
+            errors.append(&mut self.errors);
+
+            match declaration {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    errors.push(error);
+                    self.synchronize();
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn declaration(&mut self) -> Result<Stmt, ParseError> {
+        if self.matches(&[TokenKind::Fun]) {
+            return self.function();
+        }
+        if self.matches(&[TokenKind::Var]) {
+            return self.var_declaration();
+        }
+
+        self.statement()
+    }
+
+    fn function(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume_identifier("Expect function name.")?;
+        self.consume(TokenKind::LeftParen, "Expect '(' after function name.")?;
+
+        let mut params = vec![];
+        if !self.check(&TokenKind::RightParen) {
+            loop {
+                if params.len() >= self.max_args {
+                    let message = format!("Can't have more than {} parameters.", self.max_args);
+                    return Err(self.error(self.peek(), &message));
+                }
+                params.push(self.consume_identifier("Expect parameter name.")?);
+
+                if !self.matches(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenKind::RightParen, "Expect ')' after parameters.")?;
+
+        self.consume(TokenKind::LeftBrace, "Expect '{' before function body.")?;
+        // A loop around the declaration doesn't enclose the body, which runs at the call
+        let loop_depth = mem::take(&mut self.loop_depth);
+        let body = self.block();
+        self.loop_depth = loop_depth;
+        let body = body?;
+
+        Ok(Stmt::Function(Rc::new(FunctionDecl { name, params, body })))
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume_identifier("Expect variable name.")?;
+
+        let initializer = if self.matches(&[TokenKind::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(
+            TokenKind::Semicolon,
+            "Expect ';' after variable declaration.",
+        )?;
+        Ok(Stmt::Var { name, initializer })
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        if self.matches(&[TokenKind::For]) {
+            return self.for_statement();
+        }
+        if self.matches(&[TokenKind::If]) {
+            return self.if_statement();
+        }
+        if self.matches(&[TokenKind::Print]) {
+            return self.print_statement();
+        }
+        if self.matches(&[TokenKind::Return]) {
+            return self.return_statement();
+        }
+        if self.matches(&[TokenKind::While]) {
+            return self.while_statement();
+        }
+        if self.matches(&[TokenKind::Break, TokenKind::Continue]) {
+            return self.loop_control_statement();
+        }
+        if self.matches(&[TokenKind::LeftBrace]) {
+            return Ok(Stmt::Block(self.block()?));
+        }
+        if self.matches(&[TokenKind::Semicolon]) {
+            return Ok(Stmt::Empty);
+        }
+
+        self.expression_statement()
+    }
+
+    /// The declarations up to the closing brace of a block, whose opening brace has already been
+    /// consumed. A syntax error in one of them is recorded and skipped over, so that the rest of
+    /// the block is still parsed as part of it.
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut statements = vec![];
+
+        while !self.check(&TokenKind::RightBrace) && !self.is_at_end() {
+            match self.declaration() {
+                Ok(statement) => statements.push(statement),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize();
+                }
+            }
+        }
+
+        self.consume(TokenKind::RightBrace, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    /// Parses a C-style `for` loop, desugaring it into the equivalent `while` loop, which runs the
+    /// increment after each time round the body:
+    ///
+    /// ```text
+    /// { initializer; while (condition) body }
+    /// ```
+    fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        use TokenKind as TK;
+
+        self.consume(TK::LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.matches(&[TK::Semicolon]) {
+            None
+        } else if self.matches(&[TK::Var]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(&TK::Semicolon) {
+            Expr::Literal(LiteralValue::Bool(true))
+        } else {
+            self.expression()?
+        };
+        self.consume(TK::Semicolon, "Expect ';' after loop condition.")?;
+
+        let increment = if self.check(&TK::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TK::RightParen, "Expect ')' after for clauses.")?;
+
+        let body = self.loop_body()?;
+
+        let mut body = Stmt::While {
+            condition,
+            body: Box::new(body),
+            increment,
+        };
+
+        if let Some(initializer) = initializer {
+            body = Stmt::Block(vec![initializer, body]);
+        }
+
+        Ok(body)
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenKind::RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = Box::new(self.statement()?);
+        // An `else` binds to the nearest `if`, since the innermost call claims it first
+        let else_branch = if self.matches(&[TokenKind::Else]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenKind::RightParen, "Expect ')' after condition.")?;
+        let body = Box::new(self.loop_body()?);
+
+        Ok(Stmt::While {
+            condition,
+            body,
+            increment: None,
+        })
+    }
+
+    /// The body of a loop, in which `break` and `continue` are allowed.
+    fn loop_body(&mut self) -> Result<Stmt, ParseError> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn loop_control_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+
+        if self.loop_depth == 0 {
+            let message = format!("Can't use '{}' outside of a loop.", keyword.lexeme);
+            let error = self.error(&keyword, &message);
+            self.errors.push(error);
+        }
+
+        self.consume(
+            TokenKind::Semicolon,
+            &format!("Expect ';' after '{}'.", keyword.lexeme),
+        )?;
+        Ok(match keyword.kind {
+            TokenKind::Break => Stmt::Break,
+            _ => Stmt::Continue,
+        })
+    }
+
+    fn print_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        let mut values = vec![self.expression()?];
+        while self.matches(&[TokenKind::Comma]) {
+            values.push(self.expression()?);
+        }
+
+        self.consume(TokenKind::Semicolon, "Expect ';' after value.")?;
+        Ok(Stmt::Print { keyword, values })
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous().clone();
+        let value = if self.check(&TokenKind::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+
+        self.consume(TokenKind::Semicolon, "Expect ';' after return value.")?;
+        Ok(Stmt::Return { keyword, value })
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let expr = self.expression()?;
+        self.consume(TokenKind::Semicolon, "Expect ';' after expression.")?;
+        Ok(Stmt::Expression(expr))
+    }
+
+    fn expression(&mut self) -> Result<Expr, ParseError> {
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Result<Expr, ParseError> {
+        let first = self.peek().span();
+        let expr = self.or()?;
+        let target = first.to(self.previous().span());
+
+        if self.matches(&[TokenKind::Equal]) {
+            let equals = self.previous().clone();
+            // Assignment is right-associative
+            let value = self.assignment()?;
+
+            return match expr {
+                Expr::Variable { name, .. } => Ok(Expr::Assign {
+                    name,
+                    value: Box::new(value),
+                    depth: Depth::default(),
+                }),
+                _ => Err(self
+                    .error(&equals, "Invalid assignment target.")
+                    .with_span(target)),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        self.logical(&[TokenKind::Or], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        self.logical(&[TokenKind::And], Self::equality)
+    }
+
+    /// Like `binary`, but building `Logical` nodes, which the interpreter short-circuits.
+    fn logical(
+        &mut self,
+        ops: &[TokenKind],
+        operand: fn(&mut Self) -> Result<Expr, ParseError>,
+    ) -> Result<Expr, ParseError> {
+        let mut expr = operand(self)?;
+
+        while self.matches(ops) {
+            let op = self.previous().clone();
+            let right = operand(self)?;
+            expr = Expr::Logical {
+                left: Box::new(expr),
+                op,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses a left-associative sequence of `operand`s separated by any of the operators `ops`.
+    fn binary(
+        &mut self,
+        ops: &[TokenKind],
+        operand: fn(&mut Self) -> Result<Expr, ParseError>,
+    ) -> Result<Expr, ParseError> {
+        let mut expr = operand(self)?;
+
+        while self.matches(ops) {
+            let op = self.previous().clone();
+            let right = operand(self)?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> Result<Expr, ParseError> {
+        use TokenKind as TK;
+        self.binary(&[TK::BangEqual, TK::EqualEqual], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
+        use TokenKind as TK;
+        self.binary(
+            &[TK::Greater, TK::GreaterEqual, TK::Less, TK::LessEqual],
+            Self::term,
+        )
+    }
+
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        use TokenKind as TK;
+        self.binary(&[TK::Minus, TK::Plus], Self::factor)
+    }
+
+    fn factor(&mut self) -> Result<Expr, ParseError> {
+        use TokenKind as TK;
+        self.binary(&[TK::Slash, TK::Star], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        use TokenKind as TK;
+
+        if self.matches(&[TK::Bang, TK::Minus]) {
+            let op = self.previous().clone();
+            let right = self.unary()?;
+            return Ok(Expr::Unary {
+                op,
+                right: Box::new(right),
+            });
+        }
+
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.primary()?;
+
+        while self.matches(&[TokenKind::LeftParen]) {
+            expr = self.finish_call(expr)?;
+        }
+
+        Ok(expr)
+    }
+
+    /// The arguments of a call to `callee`, whose opening parenthesis has already been consumed.
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, ParseError> {
+        let mut args = vec![];
+        if !self.check(&TokenKind::RightParen) {
+            loop {
+                if args.len() >= self.max_args {
+                    let message = format!("Can't have more than {} arguments.", self.max_args);
+                    return Err(self.error(self.peek(), &message));
+                }
+                args.push(self.expression()?);
+
+                if !self.matches(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+        let paren = self
+            .consume(TokenKind::RightParen, "Expect ')' after arguments.")?
+            .clone();
+
+        Ok(Expr::Call {
+            callee: Box::new(callee),
+            paren,
+            args,
+        })
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        use TokenKind as TK;
+
+        let value = match &self.peek().kind {
+            TK::False => LiteralValue::Bool(false),
+            TK::True => LiteralValue::Bool(true),
+            TK::Nil => LiteralValue::Nil,
+            TK::Literal(Literal::Number { value, raw }) => LiteralValue::Number {
+                value: *value,
+                raw: raw.clone(),
+            },
+            TK::Literal(Literal::String(s)) => LiteralValue::String(s.clone()),
+            TK::Literal(Literal::Identifier(_)) => {
+                return Ok(Expr::Variable {
+                    name: self.advance().clone(),
+                    depth: Depth::default(),
+                });
+            }
+            TK::LeftParen => {
+                self.advance();
+                let expr = self.expression()?;
+                self.consume(TK::RightParen, "Expect ')' after expression.")?;
+                return Ok(Expr::Grouping(Box::new(expr)));
+            }
+            _ => return Err(self.error(self.peek(), "Expect expression.")),
+        };
+        self.advance();
+
+        Ok(Expr::Literal(value))
+    }
+
+    /// Advances over the next token if it is any of `kinds`. Only the variant of each kind is
+    /// compared, not any literal data it carries.
+    fn matches(&mut self, kinds: &[TokenKind]) -> bool {
+        if kinds.iter().any(|kind| self.check(kind)) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume(&mut self, kind: TokenKind, message: &str) -> Result<&Token, ParseError> {
+        if self.check(&kind) {
+            Ok(self.advance())
+        } else {
+            Err(self.error(self.peek(), message))
+        }
+    }
+
+    /// Like `consume`, but for an identifier: `check` can't distinguish identifiers from other
+    /// literal tokens.
+    fn consume_identifier(&mut self, message: &str) -> Result<Token, ParseError> {
+        match self.peek().kind {
+            TokenKind::Literal(Literal::Identifier(_)) => Ok(self.advance().clone()),
+            _ => Err(self.error(self.peek(), message)),
+        }
+    }
+
+    fn check(&self, kind: &TokenKind) -> bool {
+        mem::discriminant(&self.peek().kind) == mem::discriminant(kind)
+    }
+
+    fn advance(&mut self) -> &Token {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        self.previous()
+    }
+
+    fn is_at_end(&self) -> bool {
+        matches!(self.peek().kind, TokenKind::Eof)
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current - 1]
+    }
+
+    /// Discards tokens until the start of the next statement, so that parsing can resume after an
+    /// error without producing a cascade of spurious follow-on errors. A `}` that closes the
+    /// current block is left for it, while any block opened by discarded tokens is discarded
+    /// whole, so that its statements and closing brace aren't parsed as outside of it.
+    fn synchronize(&mut self) {
+        use TokenKind as TK;
+
+        // How many blocks the discarded tokens have opened and not yet closed
+        let mut depth = 0usize;
+        // The token the error was found at is always discarded, unless it closes the block
+        let mut discarded = false;
+
+        while !self.is_at_end() {
+            match self.peek().kind {
+                TK::RightBrace if depth == 0 => return,
+                TK::Class
+                | TK::Fun
+                | TK::Var
+                | TK::For
+                | TK::If
+                | TK::While
+                | TK::Print
+                | TK::Return
+                | TK::Break
+                | TK::Continue
+                    if depth == 0 && discarded =>
+                {
+                    return
+                }
+                _ => {}
+            }
+
+            discarded = true;
+            match self.advance().kind {
+                TK::LeftBrace => depth += 1,
+                TK::RightBrace => depth -= 1,
+                TK::Semicolon if depth == 0 => return,
+                _ => {}
+            }
+        }
+    }
+
+    fn error(&self, token: &Token, message: &str) -> ParseError {
+        ParseError {
+            token: token.clone(),
+            message: message.to_owned(),
+            span: token.span(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast_printer::{print_expr, print_program};
+    use crate::interpreter::{Interpreter, Value};
+    use crate::tokens_of;
+
+    fn parse_expr(code: &str) -> Expr {
+        Parser::new(&tokens_of(code)).parse().unwrap()
+    }
+
+    fn parse_program(code: &str) -> Result<Vec<Stmt>, Vec<ParseError>> {
+        Parser::new(&tokens_of(code)).parse_program()
+    }
+
+    #[test]
+    fn number_literals_keep_their_source_text() {
+        for (code, value) in [("1.50", 1.5), ("007", 7.0), ("10.0", 10.0)] {
+            let expr = parse_expr(code);
+
+            assert_eq!(
+                expr,
+                Expr::Literal(LiteralValue::Number {
+                    value,
+                    raw: code.to_owned(),
+                })
+            );
+            assert_eq!(print_expr(&expr), code);
+            assert_eq!(Interpreter::new().evaluate(&expr), Ok(Value::Number(value)));
+        }
+    }
+
+    #[test]
+    fn expression_tree_shapes() {
+        let cases = [
+            ("1 + 2 * 3", "(+ 1 (* 2 3))"),
+            ("1 - 2 - 3", "(- (- 1 2) 3)"),
+            ("(1 + 2) * 3", "(* (group (+ 1 2)) 3)"),
+            ("-!true", "(- (! true))"),
+            ("1 < 2 == 3 >= 4", "(== (< 1 2) (>= 3 4))"),
+            ("\"a\" != nil", "(!= \"a\" nil)"),
+        ];
+
+        for (code, tree) in cases {
+            assert_eq!(print_expr(&parse_expr(code)), tree, "parsing {code:?}");
+        }
+    }
+
+    /// The operator and operands of a binary expression.
+    fn binary(expr: Expr) -> (TokenKind, Expr, Expr) {
+        match expr {
+            Expr::Binary { left, op, right } => (op.kind, *left, *right),
+            expr => panic!("expected a binary expression, got {:?}", expr),
+        }
+    }
+
+    fn number_value(expr: &Expr) -> f64 {
+        match expr {
+            Expr::Literal(LiteralValue::Number { value, .. }) => *value,
+            expr => panic!("expected a number, got {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn factors_bind_tighter_than_terms() {
+        let (op, left, right) = binary(parse_expr("1 + 2 * 3"));
+        assert_eq!(op, TokenKind::Plus);
+        assert_eq!(number_value(&left), 1.0);
+
+        let (op, left, right) = binary(right);
+        assert_eq!(op, TokenKind::Star);
+        assert_eq!((number_value(&left), number_value(&right)), (2.0, 3.0));
+    }
+
+    #[test]
+    fn binary_operators_are_left_associative() {
+        let (op, left, right) = binary(parse_expr("1 - 2 - 3"));
+        assert_eq!(op, TokenKind::Minus);
+        assert_eq!(number_value(&right), 3.0);
+
+        let (op, left, right) = binary(left);
+        assert_eq!(op, TokenKind::Minus);
+        assert_eq!((number_value(&left), number_value(&right)), (1.0, 2.0));
+    }
+
+    #[test]
+    fn grouping_and_unary_operators() {
+        let (op, left, _) = binary(parse_expr("-(1) * 2"));
+        assert_eq!(op, TokenKind::Star);
+
+        let Expr::Unary { op, right } = left else {
+            panic!("expected a unary expression, got {:?}", left);
+        };
+        assert_eq!(op.kind, TokenKind::Minus);
+        assert!(matches!(*right, Expr::Grouping(ref inner) if number_value(inner) == 1.0));
+    }
+
+    /// A call with `count` arguments, and a function with as many parameters.
+    fn call_and_declaration(count: usize) -> (String, String) {
+        let names: Vec<_> = (0..count).map(|i| format!("a{i}")).collect();
+        let names = names.join(", ");
+        (format!("f({names});"), format!("fun f({names}) {{}}"))
+    }
+
+    #[test]
+    fn calls_and_declarations_up_to_the_max_args() {
+        let (call, declaration) = call_and_declaration(DEFAULT_MAX_ARGS);
+        assert!(parse_program(&call).is_ok());
+        assert!(parse_program(&declaration).is_ok());
+    }
+
+    #[test]
+    fn calls_and_declarations_beyond_the_max_args() {
+        let (call, declaration) = call_and_declaration(DEFAULT_MAX_ARGS + 1);
+        assert_eq!(
+            parse_errors(&call),
+            [(1, "Can't have more than 255 arguments.".to_owned())]
+        );
+        assert_eq!(
+            parse_errors(&declaration),
+            [(1, "Can't have more than 255 parameters.".to_owned())]
+        );
+    }
+
+    #[test]
+    fn max_args_is_configurable() {
+        let parse = |code: &str| {
+            Parser::new(&tokens_of(code))
+                .with_max_args(2)
+                .parse_program()
+        };
+
+        let (call, declaration) = call_and_declaration(2);
+        assert!(parse(&call).is_ok());
+        assert!(parse(&declaration).is_ok());
+
+        let (call, declaration) = call_and_declaration(3);
+        let error = &parse(&call).unwrap_err()[0];
+        assert_eq!(error.message, "Can't have more than 2 arguments.");
+        assert_eq!(error.token.lexeme, "a2");
+        let error = &parse(&declaration).unwrap_err()[0];
+        assert_eq!(error.message, "Can't have more than 2 parameters.");
+        assert_eq!(error.token.lexeme, "a2");
+    }
+
+    #[test]
+    fn expression_must_use_every_token() {
+        let error = Parser::new(&tokens_of("1 2")).parse().unwrap_err();
+        assert_eq!(error.message, "Expect end of expression.");
+        assert_eq!(error.token.lexeme, "2");
+    }
+
+    #[test]
+    fn errors_are_collected_after_synchronizing() {
+        let errors = parse_program("print ;\nvar = 1;\nprint 1 +;\nprint 2;").unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|error| (error.token.line, error.message.as_str()))
+            .collect();
+
+        assert_eq!(
+            found,
+            [
+                (1, "Expect expression."),
+                (2, "Expect variable name."),
+                (3, "Expect expression."),
+            ]
+        );
+    }
+
+    #[test]
+    fn for_desugars_into_while() {
+        let program = parse_program("for (var i = 0; i < 3; i = i + 1) print i;").unwrap();
+        assert_eq!(
+            print_program(&program),
+            "(block\n  (var i 0)\n  (while (< i 3) (step (= i (+ i 1)))\n    (print i)))\n"
+        );
+    }
+
+    #[test]
+    fn for_clauses_are_optional() {
+        let program = parse_program("for (;;) print 1;").unwrap();
+        assert_eq!(print_program(&program), "(while true\n  (print 1))\n");
+    }
+
+    #[test]
+    fn stray_semicolons_are_empty_statements() {
+        assert_eq!(parse_program(";;;"), Ok(vec![Stmt::Empty; 3]));
+        assert_eq!(
+            print_program(&parse_program("print 1;;").unwrap()),
+            "(print 1)\n(;)\n"
+        );
+    }
+
+    #[test]
+    fn for_loop_with_an_empty_body() {
+        let program = parse_program("for (;;) ;").unwrap();
+        assert_eq!(print_program(&program), "(while true\n  (;))\n");
+    }
+
+    #[test]
+    fn stray_closing_brackets_at_the_top_level() {
+        for (code, message) in [
+            ("print 1;\n}", "Unexpected '}' after program."),
+            ("print 1;\n)", "Unexpected ')' after program."),
+        ] {
+            let errors = parse_program(code).unwrap_err();
+            assert_eq!(errors.len(), 1, "parsing {code:?}");
+            assert_eq!(errors[0].message, message);
+            assert_eq!(errors[0].token.line, 2);
+        }
+    }
+
+    /// The line and message of each error in `code`.
+    fn parse_errors(code: &str) -> Vec<(usize, String)> {
+        let errors = parse_program(code).unwrap_err();
+        errors
+            .iter()
+            .map(|error| (error.token.line, error.message.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn errors_inside_blocks_leave_their_closing_brace_matched() {
+        for code in [
+            "fun f() {\n  print ;\n}",
+            "while (true) { print ; }",
+            "{\n  { print ; }\n  print ;\n}",
+        ] {
+            let errors = parse_errors(code);
+            assert!(
+                errors
+                    .iter()
+                    .all(|(_, message)| message == "Expect expression."),
+                "parsing {:?}: {:?}",
+                code,
+                errors
+            );
+        }
+    }
+
+    #[test]
+    fn statements_after_an_error_inside_a_block_stay_in_the_block() {
+        // The `break` is still inside the loop, and the final statement's error is still found
+        let code = "while (true) {\n  print ;\n  break;\n}\nprint 1 +;";
+        assert_eq!(
+            parse_errors(code),
+            [
+                (2, "Expect expression.".to_owned()),
+                (5, "Expect expression.".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn break_and_continue_start_a_statement_after_an_error() {
+        let code = "while (true) {\n  var = 1 break\n  var = 2 continue\n}";
+        assert_eq!(
+            parse_errors(code),
+            [
+                (2, "Expect variable name.".to_owned()),
+                (3, "Expect ';' after 'break'.".to_owned()),
+                (4, "Expect ';' after 'continue'.".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_block_after_an_error_is_skipped_whole() {
+        let code = "fun f(a b) {\n  print a;\n}\nif (x { print 1; }\nprint 2;";
+        assert_eq!(
+            parse_errors(code),
+            [
+                (1, "Expect ')' after parameters.".to_owned()),
+                (4, "Expect ')' after if condition.".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn stray_brace_at_an_error_is_reported_once() {
+        assert_eq!(
+            parse_errors("print 1 }"),
+            [(1, "Expect ';' after value.".to_owned())]
+        );
+    }
+
+    #[test]
+    fn invalid_assignment_target_spans_the_whole_target() {
+        let code = "print 1;\n  a + b = 3;";
+        let errors = parse_program(code).unwrap_err();
+        assert_eq!(errors[0].message, "Invalid assignment target.");
+        assert_eq!(errors[0].span, Span { start: 11, len: 5 });
+
+        let diagnostic = Diagnostic::from(&errors[0]);
+        assert_eq!(
+            diagnostic.snippet(code).unwrap(),
+            "      a + b = 3;\n      ^^^^^"
+        );
+    }
+
+    #[test]
+    fn break_and_continue_must_be_in_a_loop() {
+        let errors = parse_program("break;\nwhile (true) { fun f() { continue; } }").unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+
+        assert_eq!(
+            messages,
+            [
+                "Can't use 'break' outside of a loop.",
+                "Can't use 'continue' outside of a loop.",
+            ]
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use crate::parser::{Depth, Expr, FunctionDecl, Stmt};
+use crate::{Diagnostic, Phase, Token};
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveError {
+    pub token: Token,
+    pub message: String,
+}
+
+impl From<&ResolveError> for Diagnostic {
+    fn from(error: &ResolveError) -> Self {
+        let location = format!(" at '{}'", error.token.lexeme);
+        Diagnostic::error(Phase::Resolve, error.token.line, &location, &error.message)
+            .with_span(error.token.span())
+    }
+}
+
+/// What kind of function the code being resolved is in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum FunctionKind {
+    #[default]
+    None,
+    Function,
+}
+
+/// A pass between parsing and interpreting that works out which declaration each variable refers
+/// to, and records how many scopes out it is. The interpreter then goes straight to that scope,
+/// so a closure keeps seeing the variables that were in scope where it was declared, even if a
+/// later declaration in an enclosing block shadows one of them.
+///
+/// Only local scopes are tracked; variables that aren't found in one are left as globals.
+#[derive(Debug, Default)]
+pub struct Resolver {
+    /// For each enclosing local scope, its variables and whether each has finished being
+    /// initialized
+    scopes: Vec<HashMap<String, bool>>,
+    function: FunctionKind,
+    errors: Vec<ResolveError>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolves every variable in `statements`, returning all the errors found rather than just
+    /// the first.
+    pub fn resolve(mut self, statements: &[Stmt]) -> Result<(), Vec<ResolveError>> {
+        self.statements(statements);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Expression(expr) => self.expr(expr),
+            Stmt::Print { values, .. } => {
+                for expr in values {
+                    self.expr(expr);
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.expr(initializer);
+                }
+                self.define(name);
+            }
+            Stmt::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                self.statements(statements);
+                self.scopes.pop();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
+                self.expr(condition);
+                self.statement(body);
+                if let Some(increment) = increment {
+                    self.expr(increment);
+                }
+            }
+            Stmt::Empty | Stmt::Break | Stmt::Continue => {}
+            Stmt::Function(function) => {
+                // Defined before the body is resolved, so that functions can recurse
+                self.declare(&function.name);
+                self.define(&function.name);
+                self.function(function);
+            }
+            Stmt::Return { keyword, value } => {
+                if self.function == FunctionKind::None {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+        }
+    }
+
+    fn function(&mut self, function: &FunctionDecl) {
+        let enclosing = std::mem::replace(&mut self.function, FunctionKind::Function);
+        self.scopes.push(HashMap::new());
+        for param in &function.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.statements(&function.body);
+        self.scopes.pop();
+        self.function = enclosing;
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Unary { right, .. } => self.expr(right),
+            Expr::Literal(_) => {}
+            Expr::Grouping(expr) => self.expr(expr),
+            Expr::Call { callee, args, .. } => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Variable { name, depth } => {
+                let scope = self.scopes.last();
+                if scope.and_then(|scope| scope.get(&name.lexeme)) == Some(&false) {
+                    self.error(name, "Can't read local variable in its own initializer.");
+                }
+                self.local(name, depth);
+            }
+            Expr::Assign { name, value, depth } => {
+                self.expr(value);
+                self.local(name, depth);
+            }
+        }
+    }
+
+    /// Records how many scopes out `name` was declared, if it's a local.
+    fn local(&mut self, name: &Token, depth: &Depth) {
+        let found = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.lexeme));
+        depth.set(found);
+    }
+
+    /// Adds `name` to the innermost scope, as not yet usable until its initializer has run.
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        if scope.insert(name.lexeme.clone(), false).is_some() {
+            self.error(name, "Already a variable with this name in this scope.");
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), true);
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(ResolveError {
+            token: token.clone(),
+            message: message.to_owned(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::statements_of;
+
+    /// The messages of the errors from resolving `code`, which must scan and parse.
+    fn resolve_errors(code: &str) -> Vec<String> {
+        let statements = statements_of(code);
+        match Resolver::new().resolve(&statements) {
+            Ok(()) => vec![],
+            Err(errors) => errors.into_iter().map(|error| error.message).collect(),
+        }
+    }
+
+    #[test]
+    fn return_must_be_in_a_function() {
+        assert_eq!(
+            resolve_errors("return 1;"),
+            ["Can't return from top-level code."]
+        );
+        assert_eq!(
+            resolve_errors("{ if (true) return; }"),
+            ["Can't return from top-level code."]
+        );
+        assert!(resolve_errors("fun f() { { return 1; } }").is_empty());
+        // Leaving a function's body returns to top-level code
+        assert_eq!(
+            resolve_errors("fun f() {}\nreturn;"),
+            ["Can't return from top-level code."]
+        );
+    }
+
+    #[test]
+    fn local_cannot_be_read_in_its_own_initializer() {
+        assert_eq!(
+            resolve_errors("var a = 1;\n{ var a = a; }"),
+            ["Can't read local variable in its own initializer."]
+        );
+        // Globals are looked up at runtime, so this is allowed
+        assert!(resolve_errors("var a = a;").is_empty());
+    }
+
+    #[test]
+    fn local_cannot_be_redeclared_in_the_same_scope() {
+        assert_eq!(
+            resolve_errors("{ var a; var a; }\nfun f(b, b) {}"),
+            [
+                "Already a variable with this name in this scope.",
+                "Already a variable with this name in this scope.",
+            ]
+        );
+        assert!(resolve_errors("var a; var a; { var a; }").is_empty());
+    }
+
+    #[test]
+    fn variables_resolve_to_the_scope_they_were_declared_in() {
+        let statements = statements_of("{ var a; { var b; a; b; c; } }");
+        Resolver::new().resolve(&statements).unwrap();
+
+        let Stmt::Block(outer) = &statements[0] else {
+            panic!("expected a block")
+        };
+        let Stmt::Block(inner) = &outer[1] else {
+            panic!("expected a block")
+        };
+        let depths: Vec<_> = inner[1..]
+            .iter()
+            .map(|statement| match statement {
+                Stmt::Expression(Expr::Variable { depth, .. }) => depth.get(),
+                _ => panic!("expected a variable"),
+            })
+            .collect();
+        assert_eq!(depths, [Some(1), Some(0), None]);
+    }
+}
@@ -0,0 +1,144 @@
+//! Runs a directory of Lox scripts as a test suite, in the style of the Crafting Interpreters test
+//! suite. Each script states what it should do in comments:
+//!
+//! ```text
+//! print 1 + 2; // expect: 3
+//! print nope;  // expect runtime error: Undefined variable 'nope'.
+//! ```
+//!
+//! Each script is run in a separate jlox process, and its stdout must consist of exactly the
+//! `expect` lines in order. A script with an `expect runtime error` must exit with code 70 and
+//! report that error on stderr, at the directive's line.
+
+use anyhow::{Context, Result};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+
+#[derive(Debug, Default)]
+struct Expectations {
+    output: Vec<String>,
+    /// The line and message of the expected runtime error
+    runtime_error: Option<(usize, String)>,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Self {
+        let mut expectations = Self::default();
+
+        for (i, line) in source.lines().enumerate() {
+            if let Some(start) = line.find(EXPECT) {
+                let expected = &line[start + EXPECT.len()..];
+                expectations.output.push(expected.to_owned());
+            } else if let Some(start) = line.find(EXPECT_RUNTIME_ERROR) {
+                let message = &line[start + EXPECT_RUNTIME_ERROR.len()..];
+                expectations.runtime_error = Some((i + 1, message.to_owned()));
+            }
+        }
+
+        expectations
+    }
+}
+
+/// Runs every `*.lox` file under `dir` and prints a summary, returning whether they all passed.
+pub fn run(dir: &Path) -> Result<bool> {
+    let jlox = std::env::current_exe().context("Could not find the jlox executable")?;
+
+    let mut scripts = vec![];
+    find_scripts(dir, &mut scripts)?;
+    scripts.sort();
+
+    let mut passed = 0;
+    let mut failed = 0;
+
+    for script in &scripts {
+        let failures = run_script(&jlox, script)?;
+
+        if failures.is_empty() {
+            passed += 1;
+        } else {
+            failed += 1;
+            println!("FAIL {}", script.display());
+            for failure in failures {
+                println!("     {failure}");
+            }
+        }
+    }
+
+    println!("Passed: {passed}, Failed: {failed}");
+
+    Ok(failed == 0)
+}
+
+fn find_scripts(dir: &Path, scripts: &mut Vec<PathBuf>) -> Result<()> {
+    let entries =
+        fs::read_dir(dir).with_context(|| format!("Could not read directory {}", dir.display()))?;
+
+    for entry in entries {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            find_scripts(&path, scripts)?;
+        } else if path.extension().is_some_and(|extension| extension == "lox") {
+            scripts.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs one script, returning a description of each way it didn't meet its expectations.
+fn run_script(jlox: &Path, script: &Path) -> Result<Vec<String>> {
+    let source = fs::read_to_string(script)
+        .with_context(|| format!("Could not read {}", script.display()))?;
+    let expectations = Expectations::parse(&source);
+
+    let output = Command::new(jlox)
+        .arg(script)
+        .output()
+        .with_context(|| format!("Could not run {}", script.display()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let mut failures = vec![];
+
+    let actual: Vec<_> = stdout.lines().collect();
+    if actual != expectations.output {
+        failures.push(format!(
+            "expected output {:?}, got {:?}",
+            expectations.output, actual
+        ));
+    }
+
+    let expected_code = match &expectations.runtime_error {
+        Some((line, message)) => {
+            let expected = format!("[line {line}] Error: {message}");
+            let actual = stderr.lines().next().unwrap_or_default();
+            if actual != expected {
+                failures.push(format!(
+                    "expected runtime error {expected:?}, got {actual:?}"
+                ));
+            }
+            70
+        }
+        None => {
+            if !stderr.is_empty() {
+                failures.push(format!("unexpected errors: {:?}", stderr.trim_end()));
+            }
+            0
+        }
+    };
+
+    if output.status.code() != Some(expected_code) {
+        failures.push(format!(
+            "expected exit code {expected_code}, got {:?}",
+            output.status.code()
+        ));
+    }
+
+    Ok(failures)
+}
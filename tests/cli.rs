@@ -0,0 +1,189 @@
+//! Runs the jlox binary with its command-line options.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Output, Stdio};
+
+fn jlox(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jlox-rs"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn timings_are_reported_for_each_phase() {
+    let output = jlox(&["--timings", "tests/fixtures/cli/add.lox"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let phases: Vec<_> = stderr
+        .lines()
+        .map(|line| line.split(':').next().unwrap().trim())
+        .collect();
+
+    assert_eq!(phases, ["scan", "parse", "resolve", "interpret"]);
+    // The program's own output is kept apart from the timings
+    assert_eq!(output.stdout, b"3\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+/// Types `input` into the REPL, returning its output.
+fn repl(input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jlox-rs"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn repl_prints_expression_values_and_keeps_variables() {
+    let output = repl("var a = 1;\na + 2\nprint a;\n");
+
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "> > 3\n> 1\n> ");
+    assert_eq!(output.stderr, b"");
+}
+
+#[test]
+fn repl_shows_the_line_of_an_error_in_an_earlier_declaration() {
+    let output = repl("fun f() { return \"aaaaaaaaaaaaaaaaaaaaaaa\" + 1; }\nf()\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(
+        stderr,
+        "[line 1] Error: Operands must be two numbers or two strings.\n\
+         \x20   fun f() { return \"aaaaaaaaaaaaaaaaaaaaaaa\" + 1; }\n\
+         \x20                                              ^\n"
+    );
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn trailing_brace_is_a_syntax_error() {
+    let output = jlox(&["tests/fixtures/cli/trailing_brace.lox"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(
+        stderr.lines().next(),
+        Some("[line 2] Error at '}': Unexpected '}' after program.")
+    );
+    assert_eq!(output.stdout, b"");
+    assert_eq!(output.status.code(), Some(65));
+}
+
+#[test]
+fn stats_only_counts_included_regions() {
+    let token_count = |args: &[&str]| {
+        let output = jlox(args);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        stdout.lines().next().unwrap().to_owned()
+    };
+
+    let script = "tests/fixtures/cli/conditional.lox";
+    assert_eq!(token_count(&["stats", script]), "tokens: 3");
+    assert_eq!(
+        token_count(&["--define", "EXTRA", "stats", script]),
+        "tokens: 6"
+    );
+}
+
+#[test]
+fn ast_flag_prints_the_tree_instead_of_running() {
+    let output = jlox(&["--ast", "tests/fixtures/cli/add.lox"]);
+
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "(print (+ 1 2))\n"
+    );
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn tokens_flag_prints_each_token() {
+    let output = jlox(&["--tokens", "tests/fixtures/cli/add.lox"]);
+
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "PRINT print\nNUMBER 1 1\nPLUS +\nNUMBER 2 2\nSEMICOLON ;\nEOF \n"
+    );
+    assert_eq!(output.status.code(), Some(0));
+}
+
+/// Runs jlox with an internal panic injected before it does anything else.
+fn jlox_with_a_panic(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jlox-rs"))
+        .args(args)
+        .env("JLOX_TEST_PANIC", "1")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn internal_panic_is_a_clean_error() {
+    let output = jlox_with_a_panic(&["tests/fixtures/cli/add.lox"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(
+        stderr.starts_with("internal error: panic injected by JLOX_TEST_PANIC"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("This is a bug in jlox-rs"));
+    assert!(!stderr.contains("panicked at"));
+    assert_eq!(output.stdout, b"");
+    assert_eq!(output.status.code(), Some(70));
+}
+
+#[test]
+fn internal_panic_has_a_backtrace_with_debug() {
+    let output = jlox_with_a_panic(&["--debug", "tests/fixtures/cli/add.lox"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert!(stderr.contains("panicked at"), "{}", stderr);
+    assert_eq!(output.status.code(), Some(70));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn failing_to_print_is_a_runtime_error() {
+    let full = std::fs::File::create("/dev/full").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_jlox-rs"))
+        .arg("tests/fixtures/cli/add.lox")
+        .stdout(full)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(
+        stderr.lines().next(),
+        Some("[line 1] Error: Could not write program output: No space left on device (os error 28).")
+    );
+    assert_eq!(output.status.code(), Some(70));
+}
+
+#[test]
+fn output_closed_by_the_reader_stops_the_program_quietly() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jlox-rs"))
+        .arg("tests/fixtures/cli/endless_output.lox")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Like `| head -1`: read the first line, then stop reading
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut first = String::new();
+    stdout.read_line(&mut first).unwrap();
+    drop(stdout);
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(first, "0\n");
+    assert_eq!(output.stderr, b"");
+    assert_eq!(output.status.code(), Some(141));
+}
@@ -0,0 +1,105 @@
+//! Tests of the API that hosts embedding jlox use.
+
+use jlox_rs::{CollectDiagnostics, Diagnostic, Lox, Phase, RunOutcome, Severity};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[test]
+fn error_format_renders_gcc_style() {
+    let rendered = Rc::new(RefCell::new(vec![]));
+    let sink = Rc::clone(&rendered);
+    let mut lox = Lox::with_error_format(move |diagnostic: &Diagnostic, source: &str| {
+        let severity = match diagnostic.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let line = format!(
+            "script.lox:{}:{}: {severity}: {}",
+            diagnostic.line,
+            diagnostic.column(source).unwrap_or(1),
+            diagnostic.message
+        );
+        sink.borrow_mut().push(line.clone());
+        line
+    });
+
+    let outcome = lox.run("var a = 1;\nprint a; @", &mut lox.interpreter());
+
+    assert_eq!(outcome, RunOutcome::StaticError);
+    assert_eq!(
+        *rendered.borrow(),
+        ["script.lox:2:10: error: unexpected character `@`"]
+    );
+}
+
+#[test]
+fn collect_diagnostics_keeps_every_error() {
+    let mut lox = Lox::with_diagnostics(CollectDiagnostics::default());
+
+    let outcome = lox.run("print @;\nvar = 1;", &mut lox.interpreter());
+
+    assert_eq!(outcome, RunOutcome::StaticError);
+    let diagnostics = &lox.diagnostics.diagnostics;
+    let found: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            (
+                diagnostic.phase,
+                diagnostic.line,
+                diagnostic.message.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (Phase::Scan, 1, "unexpected character `@`"),
+            (Phase::Parse, 1, "Expect expression."),
+            (Phase::Parse, 2, "Expect variable name."),
+        ]
+    );
+}
+
+#[test]
+fn run_outcome_reflects_how_the_program_failed() {
+    let mut lox = Lox::with_diagnostics(CollectDiagnostics::default());
+
+    // Each run starts afresh, whatever went wrong in the one before
+    let cases = [
+        ("var a = 1;", RunOutcome::Ok, 0),
+        ("var a = ;", RunOutcome::StaticError, 65),
+        ("print 1 - nil;", RunOutcome::RuntimeError, 70),
+        ("print 1;", RunOutcome::Ok, 0),
+    ];
+    for (code, expected, exit_code) in cases {
+        let outcome = lox.run(code, &mut lox.interpreter());
+        assert_eq!(outcome, expected, "{code}");
+        assert_eq!(outcome.exit_code(), exit_code, "{code}");
+    }
+}
+
+#[test]
+fn runtime_error_is_reported_with_its_line() {
+    let mut lox = Lox::with_diagnostics(CollectDiagnostics::default());
+
+    let outcome = lox.run("var a = 1;\nprint a - nil;", &mut lox.interpreter());
+
+    assert_eq!(outcome, RunOutcome::RuntimeError);
+    let diagnostic = &lox.diagnostics.diagnostics[0];
+    assert_eq!(diagnostic.phase, Phase::Runtime);
+    assert_eq!(diagnostic.line, 2);
+    assert_eq!(diagnostic.message, "Operands must be numbers.");
+}
+
+#[test]
+fn deep_recursion_is_a_runtime_error_on_an_ordinary_thread() {
+    let mut lox = Lox::with_diagnostics(CollectDiagnostics::default());
+
+    let code = "fun f(n) { if (n > 0) return f(n - 1) + 1; return 0; }\nprint f(999);";
+    let outcome = lox.run(code, &mut lox.interpreter());
+
+    assert_eq!(outcome, RunOutcome::RuntimeError);
+    let message = &lox.diagnostics.diagnostics[0].message;
+    assert!(message.starts_with("Stack overflow"), "{}", message);
+}
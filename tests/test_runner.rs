@@ -0,0 +1,31 @@
+//! Runs `jlox test` over the fixture scripts in `tests/fixtures/test_runner`.
+
+use std::process::{Command, Output};
+
+fn jlox_test(dir: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jlox-rs"))
+        .arg("test")
+        .arg(format!("tests/fixtures/test_runner/{dir}"))
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn passing_scripts() {
+    let output = jlox_test("passing");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert_eq!(stdout, "Passed: 2, Failed: 0\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn failing_script() {
+    let output = jlox_test("failing");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(stdout.contains("FAIL tests/fixtures/test_runner/failing/wrong_output.lox"));
+    assert!(stdout.contains(r#"expected output ["4"], got ["3"]"#));
+    assert!(stdout.ends_with("Passed: 1, Failed: 1\n"), "{}", stdout);
+    assert_eq!(output.status.code(), Some(1));
+}